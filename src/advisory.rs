@@ -1,10 +1,10 @@
 use lazy_static::lazy_static;
 use regex::Regex;
 use serde::{Deserialize, Serialize, Serializer};
+use smol_str::SmolStr;
 use std::cmp::{Ord, Ordering, PartialOrd};
 use std::convert::TryFrom;
 use std::fmt;
-use std::io::{Cursor, Write};
 use std::str::FromStr;
 use thiserror::Error;
 
@@ -12,24 +12,59 @@ type Result<T, E = AdvErr> = std::result::Result<T, E>;
 
 lazy_static! {
     static ref CVESPEC: Regex = Regex::new(r"^CVE-(\d{4})-(\d+)$").unwrap();
+    static ref GHSASPEC: Regex = Regex::new(r"^GHSA(-[0-9a-z]{4}){3}$").unwrap();
+    static ref OSVSPEC: Regex = Regex::new(r"^[A-Z][A-Z0-9]*-[A-Za-z0-9-]+$").unwrap();
 }
 
-/// Securty advisory identifier. Currently only CVEs are supported.
+/// Security advisory identifier: a CVE, a GitHub Security Advisory id, or any other OSV-style
+/// alias (`PYSEC-...`, `RUSTSEC-...`, ...).
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize)]
 #[serde(try_from = "String")]
-pub struct Advisory(u16, u64);
+pub enum Advisory {
+    Cve { year: u16, id: u64 },
+    Ghsa(SmolStr),
+    Osv(SmolStr),
+}
 
 impl Advisory {
     #[allow(unused)]
     pub fn new(year: u16, id: u64) -> Self {
-        Self(year, id)
+        Self::Cve { year, id }
     }
 
-    /// Represent myself as numeric tuple if possible. This is needed for sorting CVEs.
-    pub fn as_tuple(&self) -> (u16, u64) {
-        // let c = ;
-        // (c[1].parse().unwrap(), c[2].parse().unwrap())
-        (self.0, self.1)
+    /// Represent myself as numeric tuple if I'm a CVE, `None` for other kinds.
+    pub fn as_tuple(&self) -> Option<(u16, u64)> {
+        match self {
+            Self::Cve { year, id } => Some((*year, *id)),
+            _ => None,
+        }
+    }
+
+    /// Canonical web URL for this advisory, so ticket rendering doesn't need to know which
+    /// database backs each kind.
+    pub fn url(&self) -> String {
+        match self {
+            Self::Cve { .. } => format!("https://nvd.nist.gov/vuln/detail/{}", self),
+            Self::Ghsa(_) => format!("https://github.com/advisories/{}", self),
+            Self::Osv(_) => format!("https://osv.dev/vulnerability/{}", self),
+        }
+    }
+
+    /// Sort group: CVEs first, then GHSAs, then everything else.
+    fn discriminant(&self) -> u8 {
+        match self {
+            Self::Cve { .. } => 0,
+            Self::Ghsa(_) => 1,
+            Self::Osv(_) => 2,
+        }
+    }
+
+    /// Lexical sort key within [`Self::discriminant`] for the non-CVE kinds.
+    fn sort_key(&self) -> &str {
+        match self {
+            Self::Cve { .. } => "",
+            Self::Ghsa(s) | Self::Osv(s) => s.as_str(),
+        }
     }
 }
 
@@ -37,17 +72,28 @@ impl Advisory {
 pub enum AdvErr {
     #[error("Failed to parse CVE identifier `{}'", 0)]
     ParseCVE(String),
+    #[error("Unrecognized advisory identifier `{}'", 0)]
+    Unknown(String),
 }
 
 impl FromStr for Advisory {
     type Err = AdvErr;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let c = CVESPEC.captures(s).ok_or(AdvErr::ParseCVE(s.into()))?;
-        match (c[1].parse(), c[2].parse()) {
-            (Ok(year), Ok(id)) => Ok(Self(year, id)),
-            _ => Err(AdvErr::ParseCVE(s.into())),
+        if s.starts_with("CVE-") {
+            let c = CVESPEC.captures(s).ok_or_else(|| AdvErr::ParseCVE(s.into()))?;
+            return match (c[1].parse(), c[2].parse()) {
+                (Ok(year), Ok(id)) => Ok(Self::Cve { year, id }),
+                _ => Err(AdvErr::ParseCVE(s.into())),
+            };
+        }
+        if GHSASPEC.is_match(s) {
+            return Ok(Self::Ghsa(SmolStr::from(s)));
+        }
+        if OSVSPEC.is_match(s) {
+            return Ok(Self::Osv(SmolStr::from(s)));
         }
+        Err(AdvErr::Unknown(s.into()))
     }
 }
 
@@ -61,13 +107,25 @@ impl TryFrom<String> for Advisory {
 
 impl fmt::Display for Advisory {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "CVE-{}-{:04}", self.0, self.1)
+        match self {
+            Self::Cve { year, id } => write!(f, "CVE-{}-{:04}", year, id),
+            Self::Ghsa(id) => write!(f, "{}", id),
+            Self::Osv(id) => write!(f, "{}", id),
+        }
     }
 }
 
 impl Ord for Advisory {
     fn cmp(&self, other: &Self) -> Ordering {
-        self.as_tuple().cmp(&other.as_tuple())
+        match self.discriminant().cmp(&other.discriminant()) {
+            Ordering::Equal => match (self, other) {
+                (Self::Cve { year: y1, id: i1 }, Self::Cve { year: y2, id: i2 }) => {
+                    (y1, i1).cmp(&(y2, i2))
+                }
+                _ => self.sort_key().cmp(other.sort_key()),
+            },
+            unequal => unequal,
+        }
     }
 }
 
@@ -82,15 +140,7 @@ impl Serialize for Advisory {
     where
         S: Serializer,
     {
-        let mut buf = [0u8; 32];
-        let mut buf = Cursor::new(&mut buf[..]);
-        buf.write_fmt(format_args!("CVE-{}-{:04}", self.0, self.1))
-            .expect("BUG: CVE serialize: value too long");
-        unsafe {
-            ser.serialize_str(std::str::from_utf8_unchecked(
-                &buf.get_ref()[..buf.position() as usize],
-            ))
-        }
+        ser.serialize_str(&self.to_string())
     }
 }
 
@@ -112,6 +162,18 @@ mod test {
         assert_eq!(cve(2019, 1003544).to_string(), "CVE-2019-1003544");
     }
 
+    #[test]
+    fn fmt_ghsa_osv() {
+        assert_eq!(
+            "GHSA-xxxx-xxxx-xxxx".parse::<Advisory>().unwrap().to_string(),
+            "GHSA-xxxx-xxxx-xxxx"
+        );
+        assert_eq!(
+            "RUSTSEC-2021-0001".parse::<Advisory>().unwrap().to_string(),
+            "RUSTSEC-2021-0001"
+        );
+    }
+
     #[test]
     fn parse_cve() {
         assert_eq!(
@@ -120,21 +182,58 @@ mod test {
         );
     }
 
+    #[test]
+    fn parse_ghsa() {
+        assert_eq!(
+            "GHSA-jfh8-c2jp-5v3q".parse::<Advisory>().unwrap(),
+            Advisory::Ghsa("GHSA-jfh8-c2jp-5v3q".into())
+        );
+    }
+
+    #[test]
+    fn parse_osv() {
+        assert_eq!(
+            "PYSEC-2022-43".parse::<Advisory>().unwrap(),
+            Advisory::Osv("PYSEC-2022-43".into())
+        );
+        assert_eq!(
+            "RUSTSEC-2021-0001".parse::<Advisory>().unwrap(),
+            Advisory::Osv("RUSTSEC-2021-0001".into())
+        );
+    }
+
     #[test]
     fn parse_invalid_cves() {
-        assert_matches!("".parse::<Advisory>(), Err(AdvErr::ParseCVE { .. }));
-        assert_matches!("foo".parse::<Advisory>(), Err(AdvErr::ParseCVE { .. }));
-        assert_matches!("CVE-20".parse::<Advisory>(), Err(AdvErr::ParseCVE { .. }));
-        assert_matches!("CVE-20-1".parse::<Advisory>(), Err(AdvErr::ParseCVE { .. }));
+        assert_matches!(
+            "CVE-20".parse::<Advisory>(),
+            Err(AdvErr::ParseCVE { .. })
+        );
+        assert_matches!(
+            "CVE-20-1".parse::<Advisory>(),
+            Err(AdvErr::ParseCVE { .. })
+        );
         assert_matches!(
             "CVE-2014-".parse::<Advisory>(),
             Err(AdvErr::ParseCVE { .. })
         );
     }
 
+    #[test]
+    fn parse_unrecognized() {
+        assert_matches!("".parse::<Advisory>(), Err(AdvErr::Unknown { .. }));
+        assert_matches!("foo".parse::<Advisory>(), Err(AdvErr::Unknown { .. }));
+    }
+
     #[test]
     fn ordering() {
         assert!(cve(2019, 9999) < cve(2019, 10000));
+        // CVEs always sort before GHSAs and other OSV ids
+        assert!(cve(2019, 1) < "GHSA-xxxx-xxxx-xxxx".parse().unwrap());
+        assert!("GHSA-aaaa-aaaa-aaaa".parse::<Advisory>().unwrap() < "PYSEC-2022-1".parse().unwrap());
+        assert!(
+            "GHSA-aaaa-aaaa-aaaa".parse::<Advisory>().unwrap()
+                < "GHSA-bbbb-bbbb-bbbb".parse().unwrap()
+        );
     }
 
     #[test]
@@ -143,6 +242,10 @@ mod test {
             serde_json::to_string(&Advisory::new(2021, 134)).unwrap(),
             "\"CVE-2021-0134\""
         );
+        assert_eq!(
+            serde_json::to_string(&"GHSA-jfh8-c2jp-5v3q".parse::<Advisory>().unwrap()).unwrap(),
+            "\"GHSA-jfh8-c2jp-5v3q\""
+        );
     }
 
     #[test]
@@ -151,5 +254,9 @@ mod test {
             serde_json::from_str::<Advisory>("\"CVE-2021-12345\"").unwrap(),
             Advisory::new(2021, 12345)
         );
+        assert_eq!(
+            serde_json::from_str::<Advisory>("\"GHSA-jfh8-c2jp-5v3q\"").unwrap(),
+            "GHSA-jfh8-c2jp-5v3q".parse().unwrap()
+        );
     }
 }
@@ -1,5 +1,7 @@
+use crate::cache::{Cache, Key};
 use crate::filter::StoreContents;
-use crate::scan::{InputPkgs, VulnixRes};
+use crate::sbom;
+use crate::scan::{InputPkgs, PkgCache, VulnixRes};
 use crate::source::AllPackages;
 use crate::Roundup;
 
@@ -7,8 +9,9 @@ use anyhow::{bail, ensure, Context, Result};
 use colored::*;
 use git2::Repository;
 use lazy_static::lazy_static;
+use rayon::prelude::*;
 use regex::Regex;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use smol_str::SmolStr;
 use std::collections::HashMap;
 use std::fmt;
@@ -22,7 +25,7 @@ use thiserror::Error;
 
 /// NixOS release to scan. Note that the git rev/branch may have a different name than the release
 /// name we publish.
-#[derive(Debug, Clone, Default, Hash, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[derive(Debug, Clone, Default, Hash, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
 pub struct Branch {
     /// NixOS release name to publish in tickets
     pub name: SmolStr,
@@ -38,29 +41,15 @@ impl Branch {
         }
     }
 
-    fn checkout(&self, repo: &Path) -> Result<()> {
-        info!(
-            "Checking out {} @ {}",
-            self.name.green().bold(),
-            self.rev[0..11].yellow()
-        );
-        let status = Command::new("git")
-            .args(&["checkout", "-q", &self.rev])
-            .current_dir(repo)
-            .status()
-            .context("Cannot execute git")?;
-        ensure!(
-            status.success(),
-            "Failed to check out git revision {}",
-            self.rev.to_string()
-        );
-        Ok(())
-    }
-
     /// File path of the vulnix.json result file
     fn vulnix_json<P: AsRef<Path>>(&self, iterdir: P) -> PathBuf {
         iterdir.as_ref().join(format!("vulnix.{}.json", self.name))
     }
+
+    /// File path of the CycloneDX SBOM
+    fn sbom_json<P: AsRef<Path>>(&self, iterdir: P) -> PathBuf {
+        iterdir.as_ref().join(format!("sbom.{}.json", self.name))
+    }
 }
 
 #[derive(Debug, Error)]
@@ -114,7 +103,75 @@ fn snapshot<P: AsRef<Path>>(scan_res: &[VulnixRes], dest: P) -> Result<()> {
     )?)
 }
 
-/// Enumerates inividual checkouts of the same repo which should be scanned in turn.
+/// A scratch `git worktree` checked out at a branch's resolved rev, for the duration of a single
+/// scan. Concurrent branches each get their own worktree so they can be built independently
+/// instead of serializing on a single shared checkout. Removed again on drop, so a scan that
+/// fails partway through (or panics) never leaves worktrees littering the scratch directory.
+struct Worktree {
+    repo: PathBuf,
+    path: PathBuf,
+}
+
+impl Worktree {
+    fn add(repo: &Path, scratch: &Path, branch: &Branch) -> Result<Self> {
+        info!(
+            "Adding worktree for {} @ {}",
+            branch.name.green().bold(),
+            branch.rev[0..11].yellow()
+        );
+        if let Some(parent) = scratch.parent() {
+            fs::create_dir_all(parent).ok();
+        }
+        fs::remove_dir_all(scratch).ok();
+        let status = Command::new("git")
+            .args(&["worktree", "add", "--detach", "--force"])
+            .arg(scratch)
+            .arg(branch.rev.as_str())
+            .current_dir(repo)
+            .status()
+            .context("Cannot execute git worktree add")?;
+        ensure!(
+            status.success(),
+            "Failed to add worktree for {} @ {}",
+            branch.name,
+            branch.rev
+        );
+        Ok(Self {
+            repo: repo.to_owned(),
+            path: scratch.to_owned(),
+        })
+    }
+
+    fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for Worktree {
+    fn drop(&mut self) {
+        let removed = Command::new("git")
+            .args(&["worktree", "remove", "--force"])
+            .arg(&self.path)
+            .current_dir(&self.repo)
+            .status()
+            .map(|s| s.success())
+            .unwrap_or(false);
+        if !removed {
+            warn!(
+                "git worktree remove failed for {:?}, cleaning up manually",
+                self.path
+            );
+            fs::remove_dir_all(&self.path).ok();
+            Command::new("git")
+                .args(&["worktree", "prune"])
+                .current_dir(&self.repo)
+                .status()
+                .ok();
+        }
+    }
+}
+
+/// Enumerates individual checkouts of the same repo which should be scanned.
 #[derive(Default, Clone)]
 pub struct Branches {
     specs: Vec<Branch>,
@@ -169,8 +226,9 @@ impl Branches {
         Ok(sbb)
     }
 
-    /// Checks out all specified branches in turn, instantiates the release derivation and invokes
-    /// vulnix on it. Figures out maintainers for affected packages.
+    /// Scans all specified branches, up to `r_opt.jobs` at a time, each in its own git worktree,
+    /// instantiating the release derivation and invoking vulnix on it. Figures out maintainers
+    /// for affected packages.
     /// A snapshot of vulnix' output is saved for subsequent `-R` invocations.
     /// Returns [`ScanByBranch`] struct which is fed into [`ticket_list`].
     pub fn scan(&self, dir: &Path, r_opt: &Roundup) -> Result<ScanByBranch> {
@@ -183,48 +241,124 @@ impl Branches {
             Some(ref dir) => Some(StoreContents::from_dir(dir)?),
             None => None,
         };
-        let mut sbb = ScanByBranch::new();
-        for branch in self.iter() {
-            branch.checkout(repo)?;
-            let mut all_pkgs =
-                AllPackages::query(repo).context("nix-build packages.json failed")?;
-            if let Some(stores_filter) = filter.as_ref() {
-                all_pkgs.retain(|pi| stores_filter.is_installed(pi))
-            }
-            let patches = all_pkgs.discover_patches(repo)?;
-            let pkgs = InputPkgs::new(&all_pkgs, patches);
-            if r_opt.keep {
-                let savedpkgs = dir.join(&format!("input.{}.json", branch.name));
-                pkgs.save(&savedpkgs)
-                    .with_context(|| format!("Failed to write input pkgs to {:?}", savedpkgs))?;
-            }
-            let pkgs = pkgs.to_file()?;
-            let scan_res = VulnixRes::run_vulnix(&branch.name, &pkgs, r_opt)
-                .with_context(|| {
-                    format!(
-                        "Scan failed - keeping derivation list for reference in {:?}",
-                        pkgs.keep().expect("failed to persist tmp file").1
+        let filter_fp = filter
+            .as_ref()
+            .map(StoreContents::fingerprint)
+            .unwrap_or_else(|| "none".to_owned());
+        let vulnix_ver =
+            VulnixRes::vulnix_version(&r_opt.vulnix).unwrap_or_else(|| "unknown".to_owned());
+        let cache = Cache::open(&r_opt.cache_dir.join("scan"))?;
+        let worktree_dir = dir.join("worktrees");
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(r_opt.jobs)
+            .build()
+            .context("Failed to set up scan thread pool")?;
+        let results: Vec<Result<Option<(Branch, Vec<VulnixRes>)>>> = pool.install(|| {
+            self.par_iter()
+                .map(|branch| {
+                    scan_one(
+                        branch,
+                        repo,
+                        &worktree_dir,
+                        &dir,
+                        &filter,
+                        &filter_fp,
+                        &vulnix_ver,
+                        &cache,
+                        r_opt,
                     )
-                })?
-                .into_iter()
-                .map(|res| res.add_maintainers(&all_pkgs.packages))
-                .collect::<Vec<_>>();
-            if scan_res.is_empty() {
-                warn!(
-                    "vulnix reported no issues for {}. Please double check. Re-run with `-R`?",
-                    branch.name.yellow()
-                );
-                continue;
+                })
+                .collect()
+        });
+        let mut sbb = ScanByBranch::new();
+        for res in results {
+            if let Some((branch, scan_res)) = res? {
+                sbb.insert(branch, scan_res);
             }
-            let snapfile = branch.vulnix_json(&dir);
-            snapshot(&scan_res, &snapfile)
-                .with_context(|| format!("Cannot write vulnix results json to {:?}", snapfile))?;
-            sbb.insert(branch.clone(), scan_res);
         }
+        cache.prune(r_opt.cache_keep).ok();
         Ok(sbb)
     }
 }
 
+/// Scans a single branch: serves a cached result if one matches, otherwise adds a scratch
+/// worktree at the branch's resolved rev and runs the full `AllPackages`/vulnix pipeline in it.
+/// Returns `None` if vulnix reported no issues (nothing to insert into [`ScanByBranch`]).
+#[allow(clippy::too_many_arguments)]
+fn scan_one(
+    branch: &Branch,
+    repo: &Path,
+    worktree_dir: &Path,
+    dir: &Path,
+    filter: &Option<StoreContents>,
+    filter_fp: &str,
+    vulnix_ver: &str,
+    cache: &Cache,
+    r_opt: &Roundup,
+) -> Result<Option<(Branch, Vec<VulnixRes>)>> {
+    let key = Key::new(&[branch.rev.as_str(), filter_fp, vulnix_ver]);
+    if let Some(scan_res) = cache.get::<Vec<VulnixRes>>("vulnix", key)? {
+        info!(
+            "{} @ {}: cache hit, skipping nix-build and vulnix",
+            branch.name.green().bold(),
+            branch.rev[0..11].yellow()
+        );
+        let snapfile = branch.vulnix_json(&dir);
+        snapshot(&scan_res, &snapfile)
+            .with_context(|| format!("Cannot write vulnix results json to {:?}", snapfile))?;
+        if r_opt.sbom {
+            warn!(
+                "{}: cache hit skips the nix-build needed to refresh the SBOM; not writing one \
+                 for this iteration",
+                branch.name.yellow()
+            );
+        }
+        return Ok(Some((branch.clone(), scan_res)));
+    }
+    let wt = Worktree::add(repo, &worktree_dir.join(branch.name.as_str()), branch)?;
+    let mut all_pkgs = AllPackages::query(wt.path(), &r_opt.cache_dir, r_opt.programs_db.as_deref())
+        .context("nix-build packages.json failed")?;
+    // Patches are cached per revision only (see `discover_patches`), so they must be discovered
+    // before `--filter` narrows `all_pkgs` down to the locally installed subset - otherwise a
+    // filtered run would poison the revision-wide cache with an undersized patch map.
+    let patches = all_pkgs.discover_patches(wt.path(), &r_opt.cache_dir)?;
+    if let Some(stores_filter) = filter.as_ref() {
+        all_pkgs.retain(|pi| stores_filter.is_installed(pi))
+    }
+    let pkgs = InputPkgs::new(&all_pkgs, patches);
+    if r_opt.keep {
+        let savedpkgs = dir.join(&format!("input.{}.json", branch.name));
+        pkgs.save(&savedpkgs)
+            .with_context(|| format!("Failed to write input pkgs to {:?}", savedpkgs))?;
+    }
+    let pkg_cache = PkgCache::open(&r_opt.cache_dir.join("pkgscan"))?;
+    let scan_res = VulnixRes::run_vulnix_cached(&branch.name, &pkgs, r_opt, &pkg_cache, vulnix_ver)
+        .context("Scan failed")?
+        .into_iter()
+        .map(|res| res.add_maintainers(&all_pkgs.packages).add_programs(&all_pkgs))
+        .collect::<Vec<_>>();
+    if scan_res.is_empty() {
+        warn!(
+            "vulnix reported no issues for {}. Please double check. Re-run with `-R`?",
+            branch.name.yellow()
+        );
+        return Ok(None);
+    }
+    cache.put("vulnix", key, &scan_res)?;
+    let snapfile = branch.vulnix_json(&dir);
+    snapshot(&scan_res, &snapfile)
+        .with_context(|| format!("Cannot write vulnix results json to {:?}", snapfile))?;
+    if r_opt.sbom {
+        let bomfile = branch.sbom_json(&dir);
+        serde_json::to_writer_pretty(
+            BufWriter::new(File::create(&bomfile)?),
+            &sbom::bom(&all_pkgs, &scan_res),
+        )
+        .with_context(|| format!("Cannot write SBOM json to {:?}", bomfile))?;
+    }
+    Ok(Some((branch.clone(), scan_res)))
+}
+
 impl Deref for Branches {
     type Target = [Branch];
 
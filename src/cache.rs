@@ -0,0 +1,159 @@
+//! Content-addressed cache for the final per-branch vulnix scan result.
+//!
+//! `packages.json` itself is already cached per-revision by [`crate::source::AllPackages::query`].
+//! This sits one level up: it caches the *end result* of a branch scan (the vulnix JSON) keyed by
+//! everything that can change that result - the resolved revision, the active package filter, and
+//! the vulnix version - so [`crate::branches::Branches::scan`] can skip checkout, the nix-build,
+//! and the vulnix invocation entirely once a given combination has been seen before.
+
+use serde::{de::DeserializeOwned, Serialize};
+use std::collections::hash_map::DefaultHasher;
+use std::fmt;
+use std::fs::{self, File};
+use std::hash::{Hash, Hasher};
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("Cannot read cache entry {0:?}")]
+    Read(PathBuf, #[source] std::io::Error),
+    #[error("Cannot write cache entry {0:?}")]
+    Write(PathBuf, #[source] std::io::Error),
+    #[error("Cannot decode cache entry {0:?}")]
+    Decode(PathBuf, #[source] serde_json::Error),
+    #[error("Cannot encode cache entry {0:?}")]
+    Encode(#[source] serde_json::Error),
+}
+
+type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// Stable key for one cache entry, derived from everything that can change a scan's outcome.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Key(u64);
+
+impl Key {
+    /// Builds a key from its parts, e.g. `[resolved_rev, filter_fingerprint, vulnix_version]`.
+    pub fn new(parts: &[&str]) -> Self {
+        let mut hasher = DefaultHasher::new();
+        parts.hash(&mut hasher);
+        Self(hasher.finish())
+    }
+}
+
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:016x}", self.0)
+    }
+}
+
+/// On-disk content-addressed store, one JSON file per `(kind, key)` pair.
+pub struct Cache {
+    dir: PathBuf,
+}
+
+impl Cache {
+    pub fn open(dir: &Path) -> Result<Self> {
+        fs::create_dir_all(dir).map_err(|e| Error::Write(dir.to_owned(), e))?;
+        Ok(Self { dir: dir.to_owned() })
+    }
+
+    fn path(&self, kind: &str, key: Key) -> PathBuf {
+        self.dir.join(format!("{}.{}.json", key, kind))
+    }
+
+    /// Reads a previously [`Cache::put`] entry back, or `None` on a cache miss.
+    pub fn get<T: DeserializeOwned>(&self, kind: &str, key: Key) -> Result<Option<T>> {
+        let path = self.path(kind, key);
+        match File::open(&path) {
+            Ok(f) => Ok(Some(
+                serde_json::from_reader(BufReader::new(f)).map_err(|e| Error::Decode(path, e))?,
+            )),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(Error::Read(path, e)),
+        }
+    }
+
+    /// Writes `value` under `(kind, key)` via a temp file + rename, so a crash mid-write can
+    /// never leave [`Cache::get`] reading a half-written entry.
+    pub fn put<T: Serialize>(&self, kind: &str, key: Key, value: &T) -> Result<()> {
+        let path = self.path(kind, key);
+        let tmp = path.with_extension("tmp");
+        {
+            let f = File::create(&tmp).map_err(|e| Error::Write(tmp.clone(), e))?;
+            serde_json::to_writer(BufWriter::new(f), value).map_err(Error::Encode)?;
+        }
+        fs::rename(&tmp, &path).map_err(|e| Error::Write(path, e))
+    }
+
+    /// Prunes all but the `keep` most recently written entries, so the cache doesn't grow
+    /// unbounded as nixpkgs advances and old revisions fall out of relevance.
+    pub fn prune(&self, keep: usize) -> Result<usize> {
+        let mut entries: Vec<(PathBuf, SystemTime)> = fs::read_dir(&self.dir)
+            .map_err(|e| Error::Read(self.dir.clone(), e))?
+            .filter_map(|e| e.ok())
+            .filter_map(|e| {
+                let mtime = e.metadata().ok()?.modified().ok()?;
+                Some((e.path(), mtime))
+            })
+            .collect();
+        entries.sort_by_key(|(_, mtime)| std::cmp::Reverse(*mtime));
+        let mut pruned = 0;
+        for (path, _) in entries.into_iter().skip(keep) {
+            if fs::remove_file(&path).is_ok() {
+                pruned += 1;
+            }
+        }
+        Ok(pruned)
+    }
+}
+
+// === Tests ===
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use tempfile::TempDir;
+
+    #[test]
+    fn roundtrip_and_miss() {
+        let tmp = TempDir::new().unwrap();
+        let cache = Cache::open(tmp.path()).unwrap();
+        let key = Key::new(&["abc123", "none", "vulnix 1.0"]);
+        assert_eq!(cache.get::<Vec<u32>>("vulnix", key).unwrap(), None);
+        cache.put("vulnix", key, &vec![1, 2, 3]).unwrap();
+        assert_eq!(
+            cache.get::<Vec<u32>>("vulnix", key).unwrap(),
+            Some(vec![1, 2, 3])
+        );
+    }
+
+    #[test]
+    fn differing_inputs_yield_differing_keys() {
+        assert_ne!(
+            Key::new(&["rev1", "none", "vulnix 1.0"]),
+            Key::new(&["rev2", "none", "vulnix 1.0"])
+        );
+        assert_ne!(
+            Key::new(&["rev1", "none", "vulnix 1.0"]),
+            Key::new(&["rev1", "some-filter", "vulnix 1.0"])
+        );
+    }
+
+    #[test]
+    fn prune_keeps_only_the_newest_entries() {
+        let tmp = TempDir::new().unwrap();
+        let cache = Cache::open(tmp.path()).unwrap();
+        for i in 0..5 {
+            cache
+                .put("vulnix", Key::new(&[&i.to_string()]), &i)
+                .unwrap();
+        }
+        let pruned = cache.prune(2).unwrap();
+        assert_eq!(pruned, 3);
+        assert_eq!(fs::read_dir(tmp.path()).unwrap().count(), 2);
+    }
+}
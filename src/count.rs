@@ -1,9 +1,12 @@
+use crate::advisory::Advisory;
+use crate::ticket::Ticket;
 use crate::tracker::Tracker;
 
 use anyhow::Result;
 use lazy_static::lazy_static;
 use regex::Regex;
 use serde::Serialize;
+use similar::{ChangeTag, TextDiff};
 use std::collections::HashMap;
 
 #[derive(Debug, Default, Serialize)]
@@ -45,3 +48,125 @@ pub fn count(tracker: &dyn Tracker) -> Result<Counts> {
     };
     Ok(counts)
 }
+
+/// Machine-readable "what changed since last roundup" counts, keyed by (package name, advisory).
+#[derive(Debug, Default, Serialize)]
+pub struct Delta {
+    new_cves: usize,
+    resolved_cves: usize,
+    rescored_cves: usize,
+}
+
+/// [`Delta`] plus a human-readable line-level diff of the rendered ticket bodies.
+#[derive(Debug, Serialize)]
+pub struct DiffReport {
+    delta: Delta,
+    text_diff: String,
+}
+
+/// Computes the delta between two iterations' ticket lists, keyed by `(pname, Advisory)`:
+/// present only in `new` is a new advisory, present only in `old` is resolved, present in both
+/// with a different score is rescored.
+fn delta(old: &[Ticket], new: &[Ticket]) -> Delta {
+    let index = |tickets: &[Ticket]| -> HashMap<(String, Advisory), Option<f32>> {
+        tickets
+            .iter()
+            .flat_map(|t| {
+                t.affected
+                    .iter()
+                    .map(move |(adv, det)| ((t.pname().to_owned(), adv.clone()), det.score()))
+            })
+            .collect()
+    };
+    let old_idx = index(old);
+    let new_idx = index(new);
+    let mut d = Delta::default();
+    for (key, score) in &new_idx {
+        match old_idx.get(key) {
+            None => d.new_cves += 1,
+            Some(prev) if prev != score => d.rescored_cves += 1,
+            _ => {}
+        }
+    }
+    d.resolved_cves = old_idx.keys().filter(|k| !new_idx.contains_key(*k)).count();
+    d
+}
+
+/// Renders both iterations' tickets as Markdown and diffs them line by line.
+fn text_diff(old: &[Ticket], new: &[Ticket]) -> String {
+    let render = |tickets: &[Ticket]| -> String {
+        tickets
+            .iter()
+            .map(|t| format!("{:#}", t))
+            .collect::<Vec<_>>()
+            .join("\n")
+    };
+    let old_text = render(old);
+    let new_text = render(new);
+    let mut out = String::with_capacity(old_text.len() + new_text.len());
+    for change in TextDiff::from_lines(&old_text, &new_text).iter_all_changes() {
+        let sign = match change.tag() {
+            ChangeTag::Delete => "-",
+            ChangeTag::Insert => "+",
+            ChangeTag::Equal => " ",
+        };
+        out.push_str(sign);
+        out.push_str(&change.to_string());
+    }
+    out
+}
+
+/// Produces a "what changed since last roundup" report between two iterations' persisted ticket
+/// lists (see `ticket::save`/`ticket::load`).
+pub fn diff_report(old: &[Ticket], new: &[Ticket]) -> DiffReport {
+    DiffReport {
+        delta: delta(old, new),
+        text_diff: text_diff(old, new),
+    }
+}
+
+// === Tests ===
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::tests::{adv, pkg};
+
+    fn tkt_with(iteration: u32, pkg_spec: &str, adv_scores: &[(&str, Option<f32>)]) -> Ticket {
+        let mut t = Ticket::new(iteration, pkg(pkg_spec));
+        for (a, score) in adv_scores {
+            t.affected.insert(
+                adv(a),
+                crate::ticket::Detail::new(*score, None),
+            );
+        }
+        t
+    }
+
+    #[test]
+    fn delta_counts_new_resolved_and_rescored() {
+        let old = vec![tkt_with(
+            1,
+            "libtiff-4.0.9",
+            &[("CVE-2018-17100", Some(8.0)), ("CVE-2018-17101", Some(8.8))],
+        )];
+        let new = vec![tkt_with(
+            2,
+            "libtiff-4.0.9",
+            &[("CVE-2018-17100", Some(9.0)), ("CVE-2019-0001", None)],
+        )];
+        let d = delta(&old, &new);
+        assert_eq!(d.new_cves, 1, "CVE-2019-0001 is new");
+        assert_eq!(d.resolved_cves, 1, "CVE-2018-17101 dropped out");
+        assert_eq!(d.rescored_cves, 1, "CVE-2018-17100 changed score");
+    }
+
+    #[test]
+    fn delta_is_empty_for_unchanged_tickets() {
+        let tickets = vec![tkt_with(1, "ncurses-6.1", &[("CVE-2018-10754", Some(5.0))])];
+        let d = delta(&tickets, &tickets.clone());
+        assert_eq!(d.new_cves, 0);
+        assert_eq!(d.resolved_cves, 0);
+        assert_eq!(d.rescored_cves, 0);
+    }
+}
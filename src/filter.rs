@@ -1,9 +1,11 @@
-use crate::source::NixEnvPkg;
+use crate::source::{NixEnvPkg, Package};
 
 use anyhow::Result;
+use std::cmp::Ordering;
 use std::collections::HashSet;
 use std::fs;
 use std::path::Path;
+use std::str::FromStr;
 
 fn extract_derivations(storelisting: &str) -> impl Iterator<Item = String> + '_ {
     storelisting.lines().filter_map(|sp| {
@@ -40,10 +42,40 @@ impl StoreContents {
         if self.known.contains(pi.pkg.as_str()) {
             return true;
         }
-        pi.meta
+        if pi
+            .meta
             .outputs
             .iter()
             .any(|out| self.known.contains(&format!("{}-{}", pi.pkg, out)))
+        {
+            return true;
+        }
+        // The store listing can be stale relative to packages.json (e.g. it was captured before
+        // a redeploy): if it already carries a newer build of the same package, that build has
+        // already received whatever fix the scanned (older) version would be flagged for, so
+        // treat it as installed too instead of still reporting on a superseded version.
+        // `<name>-<version>-<output>` entries are outputs of the scanned build itself, not a newer
+        // build, so they only count via the declared outputs above.
+        let scanned = match Package::from_str(&pi.pkg) {
+            Ok(p) => p,
+            Err(_) => return false,
+        };
+        let own_output = format!("{}-", scanned.version());
+        self.known.iter().any(|name| {
+            Package::from_str(name).map_or(false, |p| {
+                p.pname() == scanned.pname()
+                    && !p.version().starts_with(&own_output)
+                    && p.version_cmp(scanned.version()) != Ordering::Less
+            })
+        })
+    }
+
+    /// Stable summary of the filter's contents, used as a cache-key ingredient so a cached scan
+    /// result doesn't survive the filter changing.
+    pub fn fingerprint(&self) -> String {
+        let mut known: Vec<&str> = self.known.iter().map(String::as_str).collect();
+        known.sort_unstable();
+        known.join(",")
     }
 }
 
@@ -96,4 +128,17 @@ mod test {
         // outputs declared but unused
         assert!(stores.is_installed(&nixenvpkg("nspr-4.21", &["out", "lib"])));
     }
+
+    #[test]
+    fn is_installed_accepts_a_newer_build_of_the_same_package() {
+        let stores = StoreContents {
+            known: vec!["libtiff-4.0.10".to_string()].into_iter().collect(),
+        };
+        // a newer build already covers whatever the older, scanned version would be flagged for
+        assert!(stores.is_installed(&nixenvpkg("libtiff-4.0.9", &[])));
+        // an older build present in the store does not cover a newer scanned version
+        assert!(!stores.is_installed(&nixenvpkg("libtiff-4.0.11", &[])));
+        // different package name entirely
+        assert!(!stores.is_installed(&nixenvpkg("libpng-1.6.0", &[])));
+    }
 }
@@ -0,0 +1,264 @@
+//! Maintainer GitHub handle validation.
+//!
+//! nixpkgs accumulates stale or misspelled `meta.maintainers[].github` handles over time, which
+//! would otherwise silently drop people from ticket notifications. This validates the
+//! deduplicated set of handles discovered during a scan against the GitHub users API, caching
+//! results (and their `ETag`s, so unchanged re-checks are free and don't count against the rate
+//! limit) on disk so repeated runs don't re-hit the API, and exposes ways to drop dead handles or
+//! rewrite renamed ones when assembling contact lists.
+
+use crate::source::{maintainer_contacts, Maintainer};
+
+use anyhow::Context;
+use colored::*;
+use reqwest::blocking::{Client, Response};
+use reqwest::header::{HeaderMap, AUTHORIZATION, ETAG, IF_NONE_MATCH};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use smol_str::SmolStr;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("HTTP request error")]
+    Request(#[from] reqwest::Error),
+    #[error("Cannot write handle cache {0:?}")]
+    CacheWrite(PathBuf, #[source] std::io::Error),
+}
+
+type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// Validation outcome for a single GitHub handle.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum HandleStatus {
+    Valid,
+    /// User exists but under a different, current login (the handle followed a rename redirect)
+    Renamed(SmolStr),
+    Nonexistent,
+}
+
+/// One cached validation result, plus the response `ETag` (if any) so a later re-check can ask
+/// GitHub "has this changed?" via a conditional request instead of spending rate limit on it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CacheEntry {
+    status: HandleStatus,
+    #[serde(default)]
+    etag: Option<String>,
+}
+
+/// On-disk cache of validated handles, keyed by the originally queried login.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct HandleCache(HashMap<SmolStr, CacheEntry>);
+
+impl HandleCache {
+    /// Loads a cache from `path`, falling back to an empty one if it doesn't exist or is corrupt.
+    pub fn load(path: &Path) -> Self {
+        File::open(path)
+            .ok()
+            .and_then(|f| serde_json::from_reader(BufReader::new(f)).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let f = File::create(path).map_err(|e| Error::CacheWrite(path.to_owned(), e))?;
+        serde_json::to_writer_pretty(BufWriter::new(f), &self.0)
+            .map_err(|e| Error::CacheWrite(path.to_owned(), e.into()))
+    }
+
+    /// Validates `handles` against the GitHub users API: freshly-seen handles are looked up from
+    /// scratch, while already-cached ones are re-checked via a conditional request (cheap, and
+    /// free of rate-limit cost on an unchanged `304`) so renames and resurrections are eventually
+    /// noticed. Throttles to one request/second to stay well under GitHub's rate limit.
+    pub fn validate<'a>(
+        &mut self,
+        client: &Client,
+        handles: impl Iterator<Item = &'a SmolStr>,
+    ) -> Result<()> {
+        for handle in handles {
+            let etag = self.0.get(handle.as_str()).and_then(|e| e.etag.clone());
+            let entry = match lookup(client, handle, etag.as_deref())? {
+                Some(entry) => entry,
+                // 304 Not Modified: nothing changed, keep the cached status as-is.
+                None => {
+                    thread::sleep(Duration::from_secs(1));
+                    continue;
+                }
+            };
+            match &entry.status {
+                HandleStatus::Nonexistent => warn!(
+                    "GitHub handle {} does not exist (stale maintainer entry?)",
+                    handle.red()
+                ),
+                HandleStatus::Renamed(to) => {
+                    warn!("GitHub handle {} was renamed to {}", handle.yellow(), to)
+                }
+                HandleStatus::Valid => {}
+            }
+            self.0.insert(handle.clone(), entry);
+            thread::sleep(Duration::from_secs(1));
+        }
+        Ok(())
+    }
+
+    /// Maintainer contacts with known-nonexistent GitHub logins removed. Handles that haven't
+    /// been validated yet (not present in the cache) are kept, erring on the side of notifying.
+    pub fn valid_contacts<'a>(&self, maintainers: &'a [Maintainer]) -> Vec<&'a SmolStr> {
+        maintainer_contacts(maintainers)
+            .into_iter()
+            .filter(|g| !self.is_nonexistent(g))
+            .collect()
+    }
+
+    /// Whether `handle` is known to be a dead (nonexistent) GitHub login.
+    pub fn is_nonexistent(&self, handle: &str) -> bool {
+        matches!(
+            self.0.get(handle).map(|e| &e.status),
+            Some(HandleStatus::Nonexistent)
+        )
+    }
+
+    /// Rewrites `m`'s GitHub handle in place to its current login if it's known to have been
+    /// renamed, so ticket bodies and tracker pings address the account GitHub actually resolves.
+    pub fn rewrite_renamed(&self, m: &mut Maintainer) {
+        if let Maintainer::Structured {
+            github: Some(g), ..
+        } = m
+        {
+            if let Some(CacheEntry {
+                status: HandleStatus::Renamed(to),
+                ..
+            }) = self.0.get(g.as_str())
+            {
+                *g = to.clone();
+            }
+        }
+    }
+}
+
+/// Looks up a single handle, sending `etag` as `If-None-Match` when we have one on file. Returns
+/// `None` on a `304 Not Modified` response (caller should keep whatever it already had cached).
+fn lookup(client: &Client, handle: &str, etag: Option<&str>) -> Result<Option<CacheEntry>> {
+    let url = format!("https://api.github.com/users/{}", handle);
+    let mut req = client.get(&url);
+    if let Some(etag) = etag {
+        req = req.header(IF_NONE_MATCH, etag);
+    }
+    let res = req.send()?;
+    if res.status() == StatusCode::NOT_MODIFIED {
+        return Ok(None);
+    }
+    if res.status() == StatusCode::NOT_FOUND {
+        return Ok(Some(CacheEntry {
+            status: HandleStatus::Nonexistent,
+            etag: None,
+        }));
+    }
+    let res = res.error_for_status()?;
+    let etag = response_etag(&res);
+    let body: serde_json::Value = res.json()?;
+    let login = body["login"].as_str().unwrap_or(handle);
+    let status = if login.eq_ignore_ascii_case(handle) {
+        HandleStatus::Valid
+    } else {
+        HandleStatus::Renamed(login.into())
+    };
+    Ok(Some(CacheEntry { status, etag }))
+}
+
+fn response_etag(res: &Response) -> Option<String> {
+    res.headers()
+        .get(ETAG)
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_owned)
+}
+
+/// Loads the on-disk cache at `cache_path`, validates `maintainers`' deduplicated GitHub handles
+/// (freshly or via a conditional re-check, see [`HandleCache::validate`]) and persists the
+/// updated cache. `token`, when given, is sent along so this doesn't share GitHub's ~60 req/hr
+/// unauthenticated quota with every other unauthenticated caller on the same network.
+pub fn validate_maintainers(
+    cache_path: &Path,
+    maintainers: &[Maintainer],
+    token: Option<&str>,
+) -> anyhow::Result<HandleCache> {
+    let mut cache = HandleCache::load(cache_path);
+    let mut h = HeaderMap::new();
+    if let Some(token) = token {
+        h.insert(AUTHORIZATION, format!("token {}", token).parse()?);
+    }
+    let client = Client::builder()
+        .default_headers(h)
+        .user_agent(format!(
+            "{}/{}",
+            clap::crate_name!(),
+            clap::crate_version!()
+        ))
+        .build()?;
+    let handles = maintainer_contacts(maintainers);
+    let mut seen = std::collections::HashSet::new();
+    let deduped: Vec<&SmolStr> = handles.into_iter().filter(|h| seen.insert(*h)).collect();
+    // Whatever handles got validated before a failure (e.g. the quota running out partway
+    // through) are worth keeping, so save unconditionally instead of losing that progress to `?`.
+    let validated = cache.validate(&client, deduped.into_iter());
+    cache
+        .save(cache_path)
+        .with_context(|| format!("Failed to save handle cache to {:?}", cache_path))?;
+    validated.context("Failed to validate maintainer GitHub handles")?;
+    Ok(cache)
+}
+
+// === Tests ===
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn entry(status: HandleStatus) -> CacheEntry {
+        CacheEntry { status, etag: None }
+    }
+
+    #[test]
+    fn cache_roundtrip() {
+        let tmp = tempfile::Builder::new().suffix(".json").tempfile().unwrap();
+        let mut cache = HandleCache::default();
+        cache
+            .0
+            .insert("ghost-user".into(), entry(HandleStatus::Nonexistent));
+        cache.0.insert("edolstra".into(), entry(HandleStatus::Valid));
+        cache.save(tmp.path()).unwrap();
+        let loaded = HandleCache::load(tmp.path());
+        assert_eq!(loaded.0.get("ghost-user").map(|e| &e.status), Some(&HandleStatus::Nonexistent));
+        assert_eq!(loaded.0.get("edolstra").map(|e| &e.status), Some(&HandleStatus::Valid));
+    }
+
+    #[test]
+    fn drops_nonexistent_handles() {
+        let mut cache = HandleCache::default();
+        cache
+            .0
+            .insert("ghost-user".into(), entry(HandleStatus::Nonexistent));
+        let maintainers = vec![
+            Maintainer::new("ghost-user"),
+            Maintainer::new("edolstra"),
+        ];
+        assert_eq!(cache.valid_contacts(&maintainers), vec!["edolstra"]);
+    }
+
+    #[test]
+    fn rewrites_renamed_handle() {
+        let mut cache = HandleCache::default();
+        cache.0.insert(
+            "old-login".into(),
+            entry(HandleStatus::Renamed("new-login".into())),
+        );
+        let mut m = Maintainer::new("old-login");
+        cache.rewrite_renamed(&mut m);
+        assert_eq!(m, Maintainer::new("new-login"));
+    }
+}
@@ -3,8 +3,12 @@ extern crate log;
 
 mod advisory;
 mod branches;
+mod cache;
 mod count;
 mod filter;
+mod handles;
+mod reconcile;
+mod sbom;
 mod scan;
 mod source;
 #[cfg(test)]
@@ -16,12 +20,15 @@ use crate::branches::{Branch, Branches};
 use crate::ticket::Ticket;
 use crate::tracker::Tracker;
 
-use anyhow::{bail, Context, Error};
+use anyhow::{anyhow, bail, Context, Error};
 use colored::*;
 use env_logger::Env;
 use std::borrow::Borrow;
 use std::io::stdout;
 use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::thread;
+use std::time::Duration;
 use structopt::StructOpt;
 
 type Result<T, E = Error> = std::result::Result<T, E>;
@@ -51,6 +58,31 @@ pub struct Opt {
     /// Alternatively set the GITHUB_TOKEN environment variable
     #[structopt(short, long, global = true, env = "GITHUB_TOKEN")]
     github_token: Option<String>,
+    /// Tracker backend to use for --repo
+    ///
+    /// 'forgejo' also covers Gitea instances, which expose the same API. The instance base URL
+    /// is taken from the FORGEJO_URL environment variable.
+    #[structopt(
+        long,
+        global = true,
+        value_name = "github|forgejo",
+        default_value = "github"
+    )]
+    backend: Backend,
+    /// Forgejo/Gitea access token, used when --backend=forgejo
+    ///
+    /// Alternatively set the FORGEJO_TOKEN environment variable
+    #[structopt(long, global = true, env = "FORGEJO_TOKEN")]
+    forgejo_token: Option<String>,
+    /// Index tickets into this Elasticsearch index instead of creating GitHub issues
+    ///
+    /// The cluster URL is taken from the ELASTICSEARCH_URL environment variable.
+    #[structopt(long, global = true, value_name = "INDEX")]
+    elastic_index: Option<String>,
+    /// What to do if --elastic-index already exists: 'abort' keeps appending to it, 'recreate'
+    /// drops and rebuilds it
+    #[structopt(long, global = true, value_name = "abort|recreate", default_value = "abort")]
+    elastic_on_exists: String,
     #[structopt(subcommand)]
     command: Cmd,
 }
@@ -60,18 +92,67 @@ impl Default for Opt {
         Opt {
             repo: None,
             github_token: None,
+            backend: Backend::GitHub,
+            forgejo_token: None,
+            elastic_index: None,
+            elastic_on_exists: "abort".to_owned(),
             command: Cmd::Roundup(Roundup::default()),
             basedir: PathBuf::from("iterations"),
         }
     }
 }
 
+/// Which tracker API `--repo` refers to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    GitHub,
+    Forgejo,
+}
+
+impl FromStr for Backend {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "github" => Ok(Self::GitHub),
+            "forgejo" => Ok(Self::Forgejo),
+            _ => bail!("Invalid --backend {:?}, expected 'github' or 'forgejo'", s),
+        }
+    }
+}
+
 #[derive(Debug, Clone, StructOpt)]
 pub enum Cmd {
     /// Creates vulnerability roundup and (optionally) submit issues to a tracker.
     Roundup(Roundup),
     /// Counts open issues and CVEs.
     Count,
+    /// Watches `nixpkgs` for new commits and re-runs the roundup whenever something moved.
+    Watch(Watch),
+    /// Shows new/resolved/rescored advisories between two iterations.
+    Diff(Diff),
+}
+
+#[derive(Debug, Clone, StructOpt)]
+pub struct Diff {
+    /// Earlier iteration number to diff against
+    #[structopt(value_name = "OLD")]
+    old: u32,
+    /// Later iteration number
+    #[structopt(value_name = "NEW")]
+    new: u32,
+}
+
+#[derive(Debug, Clone, StructOpt)]
+pub struct Watch {
+    #[structopt(flatten)]
+    roundup: Roundup,
+    /// Poll interval in seconds
+    #[structopt(long, value_name = "SECS", default_value = "300")]
+    interval: u64,
+    /// Run a single roundup iteration right away and exit instead of polling forever
+    #[structopt(long)]
+    once: bool,
 }
 
 #[derive(Debug, Clone, StructOpt, Default)]
@@ -115,6 +196,32 @@ pub struct Roundup {
     /// Only consider packages found in at least one Nix store dump in DIR
     #[structopt(short, long, value_name = "DIR", parse(from_os_str))]
     filter: Option<PathBuf>,
+    /// Also write a CycloneDX SBOM with embedded VEX for each branch to sbom.BRANCH.json
+    #[structopt(long)]
+    sbom: bool,
+    /// Directory for the package/patch query cache, keyed by nixpkgs commit id
+    #[structopt(
+        long,
+        value_name = "DIR",
+        default_value = "cache",
+        parse(from_os_str)
+    )]
+    cache_dir: PathBuf,
+    /// Number of most recent scan cache entries to keep, counted across all branches; older ones
+    /// are pruned after each run
+    #[structopt(long, value_name = "N", default_value = "50")]
+    cache_keep: usize,
+    /// Maximum number of branches to scan concurrently, each in its own git worktree. 0 lets
+    /// rayon pick a value based on the available CPUs
+    #[structopt(long, value_name = "N", default_value = "1")]
+    jobs: usize,
+    /// Path to a `programs.sqlite` database (maps executables to the nixpkgs attribute that
+    /// provides them), used to list affected binaries in ticket bodies
+    ///
+    /// Overrides the copy auto-detected alongside the queried package list, if any. The feature
+    /// is simply skipped if neither is present.
+    #[structopt(long, value_name = "PATH", parse(from_os_str))]
+    programs_db: Option<PathBuf>,
     /// Nth survey iteration
     #[structopt(value_name = "N")]
     iteration: u32,
@@ -134,17 +241,47 @@ impl Roundup {
 }
 
 fn tracker(opt: &Opt, ping_maintainers: bool) -> Result<Box<dyn Tracker>> {
-    Ok(match (&opt.repo, &opt.github_token) {
-        (Some(repo), Some(token)) => Box::new(tracker::GitHub::new(
-            token.to_string(),
-            repo,
-            ping_maintainers,
-        )?),
-        (Some(_), None) => bail!(
-            "No GitHub access token given either as option or via the GITHUB_TOKEN environment \
-             variable"
-        ),
-        (_, _) => Box::new(tracker::File::new()),
+    if let Some(index) = &opt.elastic_index {
+        let url = std::env::var(tracker::URL_VAR).with_context(|| {
+            format!("--elastic-index given but {} is not set", tracker::URL_VAR)
+        })?;
+        let on_exists = opt
+            .elastic_on_exists
+            .parse()
+            .with_context(|| format!("Invalid --elastic-on-exists {:?}", opt.elastic_on_exists))?;
+        return Ok(Box::new(tracker::Elastic::new(&url, index, on_exists)?));
+    }
+    let repo = match &opt.repo {
+        Some(repo) => repo,
+        None => return Ok(Box::new(tracker::File::new())),
+    };
+    Ok(match opt.backend {
+        Backend::GitHub => match &opt.github_token {
+            Some(token) => Box::new(tracker::GitHub::new(
+                token.to_string(),
+                repo,
+                ping_maintainers,
+            )?),
+            None => bail!(
+                "No GitHub access token given either as option or via the GITHUB_TOKEN \
+                 environment variable"
+            ),
+        },
+        Backend::Forgejo => {
+            let token = opt.forgejo_token.clone().ok_or_else(|| {
+                anyhow!(
+                    "No Forgejo access token given either as option or via the FORGEJO_TOKEN \
+                     environment variable"
+                )
+            })?;
+            let url = std::env::var(tracker::FORGEJO_URL_VAR).with_context(|| {
+                format!(
+                    "--backend forgejo given but {} is not set",
+                    tracker::FORGEJO_URL_VAR
+                )
+            })?;
+            Box::new(tracker::Forgejo::new(&url, token, repo, ping_maintainers)?)
+        }
     })
 }
 
@@ -169,7 +306,33 @@ fn roundup(opt: &Opt, r_opt: &Roundup) -> Result<()> {
     } else {
         branches.scan(&iterdir, r_opt)?
     };
-    let tickets = ticket::ticket_list(r_opt.iteration, sbb);
+    let mut tickets = ticket::ticket_list(r_opt.iteration, sbb);
+    if r_opt.ping_maintainers {
+        let all_maintainers: Vec<_> = tickets.iter().flat_map(|t| t.maintainers.clone()).collect();
+        let cache_path = opt.basedir.join("handles.json");
+        let token = opt.github_token.as_deref();
+        let cache = handles::validate_maintainers(&cache_path, &all_maintainers, token)
+            .context("Failed to validate maintainer GitHub handles")?;
+        for tkt in &mut tickets {
+            for m in &mut tkt.maintainers {
+                cache.rewrite_renamed(m);
+            }
+            tkt.maintainers.retain(|m| match m {
+                crate::source::Maintainer::Structured {
+                    github: Some(g), ..
+                } => !cache.is_nonexistent(g),
+                _ => true,
+            });
+        }
+    }
+    ticket::save(&tickets, &iterdir)
+        .with_context(|| format!("Failed to persist ticket list to {:?}", iterdir))?;
+    let tickets = if tracker.reconciles() {
+        reconcile::reconcile(tracker.borrow(), tickets)
+            .context("Failed to reconcile tickets with existing tracker issues")?
+    } else {
+        tickets
+    };
     if !tickets.is_empty() {
         info!("Creating issues in {} tracker", tracker.name().green());
         tracker.create_issues(tickets, &r_opt.iterdir(&opt.basedir))?;
@@ -177,12 +340,84 @@ fn roundup(opt: &Opt, r_opt: &Roundup) -> Result<()> {
     Ok(())
 }
 
+fn diff(opt: &Opt, d: &Diff) -> Result<()> {
+    let old = ticket::load(&opt.basedir.join(d.old.to_string()))?;
+    let new = ticket::load(&opt.basedir.join(d.new.to_string()))?;
+    serde_json::to_writer_pretty(stdout().lock(), &count::diff_report(&old, &new))
+        .context("broken pipe")
+}
+
+/// State of the world as observed by one [`watch`] poll: resolved branch revs plus the output of
+/// `vulnix --version`. Updates to vulnix' vulnerability database that leave that output unchanged
+/// are not observed. Two consecutive polls returning equal [`WatchState`]s mean things have
+/// settled and are worth scanning.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct WatchState {
+    revs: Vec<Branch>,
+    vulnix: Option<String>,
+}
+
+impl WatchState {
+    fn observe(r_opt: &Roundup) -> Result<Self> {
+        let branches = Branches::with_repo(&r_opt.branches, &r_opt.nixpkgs)?;
+        Ok(Self {
+            revs: branches.to_vec(),
+            vulnix: scan::VulnixRes::vulnix_version(&r_opt.vulnix),
+        })
+    }
+}
+
+/// Polls `r_opt.nixpkgs` and the vulnix version (see [`WatchState`]) for changes, re-running
+/// [`roundup`] once a change has settled (i.e. was observed on two consecutive polls). The iteration counter is
+/// only bumped after a successful scan; a failed roundup is logged and retried next tick.
+fn watch(opt: &Opt, w: &Watch) -> Result<()> {
+    let mut r_opt = w.roundup.clone();
+    let mut settled: Option<WatchState> = None;
+    let mut pending: Option<WatchState> = None;
+    loop {
+        let current = WatchState::observe(&r_opt).context("Failed to poll nixpkgs/vulnix state")?;
+        let changed = settled.as_ref() != Some(&current);
+        // Nothing to debounce against on the very first poll: scan straight away.
+        let should_scan = changed && (settled.is_none() || pending.as_ref() == Some(&current));
+        if changed && !should_scan {
+            debug!("Detected nixpkgs/vulnix change, waiting for it to settle");
+            pending = Some(current.clone());
+        }
+        if should_scan {
+            info!(
+                "Starting roundup iteration {}",
+                r_opt.iteration.to_string().green()
+            );
+            match roundup(opt, &r_opt) {
+                Ok(()) => {
+                    settled = Some(current);
+                    pending = None;
+                    r_opt.iteration += 1;
+                }
+                Err(e) => {
+                    error!("Roundup failed, will retry next tick: {:#}", e);
+                    // Keep `pending` set to the state that just failed, so the very next poll
+                    // (which will observe this same settled-looking state again) immediately
+                    // re-triggers `should_scan` instead of waiting for it to "settle" twice more.
+                    pending = Some(current);
+                }
+            }
+        }
+        if w.once {
+            return Ok(());
+        }
+        thread::sleep(Duration::from_secs(w.interval));
+    }
+}
+
 fn run() -> Result<()> {
     dotenv::dotenv().ok();
     let opt = Opt::from_args();
     match opt.command {
         Cmd::Roundup(ref r) => roundup(&opt, r),
         Cmd::Count => count(&opt),
+        Cmd::Watch(ref w) => watch(&opt, w),
+        Cmd::Diff(ref d) => diff(&opt, d),
     }
 }
 
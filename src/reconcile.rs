@@ -0,0 +1,174 @@
+//! Reconciles freshly scanned [`Ticket`]s against already-open tracker issues instead of always
+//! filing new ones.
+//!
+//! The rendered ticket body is purely a function of the latest scan, but the live issue may carry
+//! checklist state a maintainer entered by hand ("I already backported the fix"). This merges the
+//! two: advisories already checked off stay checked, advisories that dropped out of the scan
+//! (resolved) are appended checked, and newly-appeared advisories show up unchecked. Merging is
+//! driven as a multipass fixer - render, diff against the live body, apply, re-read - until the
+//! body stops changing, since the first pass's appended/flipped lines must themselves survive a
+//! second read unchanged before we call it settled.
+
+use crate::advisory::Advisory;
+use crate::source::Package;
+use crate::ticket::Ticket;
+use crate::tracker::{Issue, Tracker};
+
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::str::FromStr;
+
+/// Give up reconciling a single issue after this many passes rather than looping forever on a
+/// body that never settles.
+const MAX_PASSES: usize = 5;
+
+lazy_static! {
+    static ref CHECKLIST_ITEM: Regex = Regex::new(r"(?m)^\* \[([ xX])\] \[([^\]]+)\]").unwrap();
+    static ref TITLE_PKG: Regex = Regex::new(r"^Vulnerability roundup \d+: (\S+): ").unwrap();
+}
+
+/// Parses `* [ ]`/`* [x]` checklist lines out of an issue body, keyed by advisory id.
+fn parse_checklist(body: &str) -> HashMap<Advisory, bool> {
+    CHECKLIST_ITEM
+        .captures_iter(body)
+        .filter_map(|c| {
+            let checked = !c[1].eq_ignore_ascii_case(" ");
+            c[2].parse::<Advisory>().ok().map(|a| (a, checked))
+        })
+        .collect()
+}
+
+/// Finds the open issue tracking the same package (by [`Package::pname`], ignoring version
+/// drift between iterations), if any.
+fn find_issue<'a>(issues: &'a [Issue], tkt: &Ticket) -> Option<&'a Issue> {
+    issues.iter().find(|i| {
+        TITLE_PKG
+            .captures(&i.title)
+            .and_then(|c| Package::from_str(&c[1]).ok())
+            .map_or(false, |p| p.pname() == tkt.pname())
+    })
+}
+
+/// Renders `tkt`'s body with checklist state merged in from `prior`: advisories still affected
+/// keep whatever check state `prior` already recorded, and advisories in `prior` that dropped out
+/// of `tkt.affected` (resolved by this scan) are appended, checked.
+fn merge_body(tkt: &Ticket, prior: &HashMap<Advisory, bool>) -> String {
+    let mut body = String::with_capacity(4096);
+    tkt.render(&mut body, false).ok();
+    for (adv, checked) in prior {
+        if tkt.affected.contains_key(adv) {
+            if *checked {
+                body = body.replacen(
+                    &format!("* [ ] [{}]", adv),
+                    &format!("* [x] [{}]", adv),
+                    1,
+                );
+            }
+        } else {
+            writeln!(body, "* [x] [{adv}]({url}) resolved", adv = adv, url = adv.url()).ok();
+        }
+    }
+    body
+}
+
+/// Reconciles `tickets` against already-open issues returned by `tracker.search()`: matching
+/// issues get their body rewritten with merged checklist state; tickets without a matching open
+/// issue are returned so the caller can file them as fresh issues.
+pub fn reconcile(
+    tracker: &dyn Tracker,
+    tickets: Vec<Ticket>,
+) -> Result<Vec<Ticket>, crate::tracker::Error> {
+    let issues = tracker.search()?;
+    let mut fresh = Vec::new();
+    for tkt in tickets {
+        match find_issue(&issues, &tkt) {
+            Some(issue) => {
+                let mut body = issue.body.clone();
+                for _ in 0..MAX_PASSES {
+                    let prior = parse_checklist(&body);
+                    let next = merge_body(&tkt, &prior);
+                    if next == body {
+                        break;
+                    }
+                    body = next;
+                }
+                tracker.update_issue(issue, &body)?;
+            }
+            None => fresh.push(tkt),
+        }
+    }
+    Ok(fresh)
+}
+
+// === Tests ===
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::tests::{adv, pkg};
+
+    use maplit::hashmap;
+
+    fn issue(title: &str, body: &str) -> Issue {
+        Issue {
+            title: title.into(),
+            body: body.into(),
+            ..Issue::default()
+        }
+    }
+
+    #[test]
+    fn parses_checklist_state() {
+        let body = "\
+* [ ] [CVE-2019-0001](https://nvd.nist.gov/vuln/detail/CVE-2019-0001) CVSSv3=5.0 (br0)
+* [x] [CVE-2019-0002](https://nvd.nist.gov/vuln/detail/CVE-2019-0002) CVSSv3=6.0 (br0)
+";
+        assert_eq!(
+            parse_checklist(body),
+            hashmap! { adv("CVE-2019-0001") => false, adv("CVE-2019-0002") => true }
+        );
+    }
+
+    #[test]
+    fn finds_issue_by_pname_ignoring_version() {
+        let issues = vec![issue(
+            "Vulnerability roundup 1: libtiff-4.0.8: 1 advisory",
+            "",
+        )];
+        let tkt = Ticket::new(2, pkg("libtiff-4.0.9"));
+        assert_eq!(find_issue(&issues, &tkt).unwrap().title, issues[0].title);
+    }
+
+    #[test]
+    fn merge_keeps_manual_checks_and_resolves_dropped_cves() {
+        let mut tkt = Ticket::new(2, pkg("libtiff-4.0.9"));
+        tkt.affected
+            .insert(adv("CVE-2019-0001"), Default::default());
+        let prior = hashmap! {
+            adv("CVE-2019-0001") => true,
+            adv("CVE-2019-0002") => false,
+        };
+        let body = merge_body(&tkt, &prior);
+        assert!(body.contains("* [x] [CVE-2019-0001]"));
+        assert!(body.contains("* [x] [CVE-2019-0002](https://nvd.nist.gov/vuln/detail/CVE-2019-0002) resolved"));
+    }
+
+    #[test]
+    fn reconcile_reaches_fixed_point_in_two_passes() {
+        let mut tkt = Ticket::new(3, pkg("libtiff-4.0.9"));
+        tkt.affected
+            .insert(adv("CVE-2019-0001"), Default::default());
+        let original = "\
+Vulnerability roundup 2: libtiff-4.0.9: 2 advisories
+
+* [x] [CVE-2019-0001](https://nvd.nist.gov/vuln/detail/CVE-2019-0001) (br0)
+* [ ] [CVE-2019-0002](https://nvd.nist.gov/vuln/detail/CVE-2019-0002) (br0)
+";
+        let prior = parse_checklist(original);
+        let once = merge_body(&tkt, &prior);
+        let twice = merge_body(&tkt, &parse_checklist(&once));
+        assert_eq!(once, twice, "merge should reach a fixed point after one re-read");
+    }
+}
@@ -0,0 +1,211 @@
+//! CycloneDX 1.5 SBOM + embedded VEX export.
+//!
+//! Turns a branch's scanned package set, together with the advisories vulnix matched against it,
+//! into a machine-readable vulnerability document so downstream tooling can ingest survey results
+//! alongside other SBOM pipelines.
+
+use crate::advisory::Advisory;
+use crate::scan::VulnixRes;
+use crate::source::{AllPackages, Package};
+
+use chrono::Utc;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::str::FromStr;
+
+const BOM_FORMAT: &str = "CycloneDX";
+const SPEC_VERSION: &str = "1.5";
+
+#[derive(Debug, Serialize)]
+pub struct Bom {
+    #[serde(rename = "bomFormat")]
+    bom_format: &'static str,
+    #[serde(rename = "specVersion")]
+    spec_version: &'static str,
+    version: u32,
+    metadata: Metadata,
+    components: Vec<Component>,
+    vulnerabilities: Vec<Vulnerability>,
+}
+
+#[derive(Debug, Serialize)]
+struct Metadata {
+    timestamp: String,
+}
+
+#[derive(Debug, Serialize)]
+struct Component {
+    #[serde(rename = "bom-ref")]
+    bom_ref: String,
+    #[serde(rename = "type")]
+    kind: &'static str,
+    name: String,
+    version: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    purl: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct Vulnerability {
+    id: String,
+    affects: Vec<Affect>,
+    analysis: Analysis,
+}
+
+#[derive(Debug, Serialize)]
+struct Affect {
+    #[serde(rename = "ref")]
+    bom_ref: String,
+}
+
+#[derive(Debug, Serialize)]
+struct Analysis {
+    state: &'static str,
+}
+
+/// Builds a `pkg:nix/<pname>@<version>` package URL, percent-encoding purl-reserved characters in
+/// the version. Returns `None` if `pkg` doesn't split into a name/version pair.
+fn purl(pkg: &str) -> Option<String> {
+    let p = Package::from_str(pkg).ok()?;
+    Some(format!(
+        "pkg:nix/{}@{}",
+        p.pname(),
+        percent_encode(p.version())
+    ))
+}
+
+/// Percent-encodes everything but purl's unreserved characters (RFC 3986 `unreserved` set).
+fn percent_encode(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for b in s.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                out.push(b as char)
+            }
+            _ => out.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    out
+}
+
+/// Renders a CycloneDX 1.5 document with embedded VEX for one branch's scan result.
+///
+/// `all` provides the full package set (for components and each package's already-known
+/// `knownVulnerabilities`), `scan_res` the advisories vulnix actually matched.
+pub fn bom(all: &AllPackages, scan_res: &[VulnixRes]) -> Bom {
+    let mut by_pkg: HashMap<Package, &str> = HashMap::with_capacity(all.packages.len());
+    let mut components = Vec::with_capacity(all.packages.len());
+    for (attr, pi) in &all.packages {
+        let (name, version, purl) = match Package::from_str(&pi.pkg) {
+            Ok(p) => {
+                by_pkg.insert(p.clone(), attr.as_str());
+                (p.pname().to_owned(), p.version().to_owned(), purl(&pi.pkg))
+            }
+            Err(_) => (pi.pkg.to_string(), String::new(), None),
+        };
+        components.push(Component {
+            bom_ref: attr.to_string(),
+            kind: "library",
+            name,
+            version,
+            purl,
+        });
+    }
+
+    let mut affects: HashMap<String, Vec<Affect>> = HashMap::new();
+    let mut known: HashMap<String, bool> = HashMap::new();
+    for res in scan_res {
+        let bom_ref = match by_pkg.get(&res.pkg) {
+            Some(r) => r.to_string(),
+            None => continue,
+        };
+        let pi = &all.packages[bom_ref.as_str()];
+        for adv in &res.affected_by {
+            let id = adv.to_string();
+            affects
+                .entry(id.clone())
+                .or_insert_with(Vec::new)
+                .push(Affect {
+                    bom_ref: bom_ref.clone(),
+                });
+            let flagged = pi
+                .meta
+                .known_vulnerabilities
+                .iter()
+                .filter_map(|k| k.parse::<Advisory>().ok())
+                .any(|k| &k == adv);
+            known.entry(id).or_insert(flagged);
+        }
+    }
+    let mut vulnerabilities: Vec<Vulnerability> = affects
+        .into_iter()
+        .map(|(id, affects)| {
+            let state = if known.get(&id).copied().unwrap_or(false) {
+                "resolved"
+            } else {
+                "exploitable"
+            };
+            Vulnerability {
+                id,
+                affects,
+                analysis: Analysis { state },
+            }
+        })
+        .collect();
+    vulnerabilities.sort_by(|a, b| a.id.cmp(&b.id));
+
+    Bom {
+        bom_format: BOM_FORMAT,
+        spec_version: SPEC_VERSION,
+        version: 1,
+        metadata: Metadata {
+            timestamp: Utc::now().to_rfc3339(),
+        },
+        components,
+        vulnerabilities,
+    }
+}
+
+// === Tests ===
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::tests::{adv, pkg};
+
+    use maplit::hashmap;
+
+    #[test]
+    fn purl_encodes_reserved_chars() {
+        assert_eq!(purl("openssl-1.0.2+d").unwrap(), "pkg:nix/openssl@1.0.2%2Bd");
+    }
+
+    #[test]
+    fn purl_none_without_version() {
+        assert!(purl("openssl").is_none());
+    }
+
+    #[test]
+    fn marks_known_vulnerabilities_resolved() {
+        let mut all = AllPackages::default();
+        all.packages = hashmap! {
+            "openssl".into() => crate::source::NixEnvPkg {
+                pkg: "openssl-1.0.2d".into(),
+                meta: crate::source::PkgMeta {
+                    known_vulnerabilities: vec!["CVE-2019-0001".into()],
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        };
+        let scan_res = vec![VulnixRes::new(pkg("openssl-1.0.2d"), vec![adv("CVE-2019-0001")])];
+        let doc = bom(&all, &scan_res);
+        assert_eq!(doc.vulnerabilities.len(), 1);
+        assert_eq!(doc.vulnerabilities[0].analysis.state, "resolved");
+        assert_eq!(doc.components.len(), 1);
+        assert_eq!(
+            doc.components[0].purl.as_deref(),
+            Some("pkg:nix/openssl@1.0.2d")
+        );
+    }
+}
@@ -4,10 +4,11 @@ use crate::Roundup;
 
 use anyhow::{ensure, Context, Result};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use std::collections::HashMap;
-use std::fs::File;
-use std::io::Write;
-use std::path::Path;
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
 use std::process::Command;
 use tempfile::NamedTempFile;
 
@@ -31,6 +32,10 @@ pub struct VulnixRes {
     pub cvssv3_basescore: ScoreMap,
     #[serde(default)]
     pub maintainers: Vec<Maintainer>,
+    /// Executables this package provides, resolved via `programs.sqlite` (see
+    /// [`AllPackages::programs_of`]). Empty if the DB wasn't available.
+    #[serde(default)]
+    pub programs: Vec<Str>,
 }
 
 type PkgMap = HashMap<Attr, NixEnvPkg>;
@@ -81,6 +86,63 @@ impl VulnixRes {
         })
     }
 
+    /// Like [`Self::run_vulnix`], but consults `cache` first (see [`PkgCache`]): packages whose
+    /// derivation, patches and known vulnerabilities haven't changed since a previous run are
+    /// served from the content-addressed cache instead of being re-scanned, which makes reruns
+    /// after a transient failure close to instant.
+    pub fn run_vulnix_cached(
+        branch_name: &str,
+        pkgs: &InputPkgs,
+        r_opt: &Roundup,
+        cache: &PkgCache,
+        vulnix_ver: &str,
+    ) -> Result<Vec<Self>> {
+        let (mut hits, misses, integrities) = cache
+            .split(pkgs, vulnix_ver)
+            .context("Failed to read package scan cache")?;
+        if misses.0.is_empty() {
+            info!(
+                "{}: all {} packages served from scan cache",
+                branch_name,
+                integrities.len()
+            );
+            return Ok(hits);
+        }
+        info!(
+            "{}: {} of {} packages changed, scanning those with vulnix",
+            branch_name,
+            misses.0.len(),
+            integrities.len()
+        );
+        let tmp = misses.to_file()?;
+        let fresh = Self::run_vulnix(branch_name, &tmp, r_opt).with_context(|| match tmp.keep() {
+            Ok((_, path)) => {
+                format!("Scan failed - keeping derivation list for reference in {:?}", path)
+            }
+            Err(e) => format!(
+                "Scan failed; additionally failed to persist {:?} for reference: {}",
+                e.file.path(),
+                e.error
+            ),
+        })?;
+        cache
+            .update(&misses, &integrities, &fresh)
+            .context("Failed to update package scan cache")?;
+        hits.extend(fresh);
+        Ok(hits)
+    }
+
+    /// `vulnix --version` output, used as a cache-key ingredient: a newer vulnix may know about
+    /// advisories an older one didn't, so a cached scan result must not survive a vulnix upgrade.
+    pub fn vulnix_version<P: AsRef<Path>>(vulnix: P) -> Option<String> {
+        Command::new(vulnix.as_ref())
+            .arg("--version")
+            .output()
+            .ok()
+            .filter(|o| o.status.success())
+            .map(|o| String::from_utf8_lossy(&o.stdout).trim().to_owned())
+    }
+
     /// Augments myself with maintainer contacts taken from pkginfo map.
     pub fn add_maintainers(mut self, pkgmap: &PkgMap) -> Self {
         for pi in pkgmap.values() {
@@ -92,10 +154,22 @@ impl VulnixRes {
         }
         self
     }
+
+    /// Augments myself with the executables I provide, looked up via `all`'s `programs.sqlite`
+    /// index (see [`AllPackages::programs_of`]).
+    pub fn add_programs(mut self, all: &AllPackages) -> Self {
+        for (attr, pi) in &all.packages {
+            // same caveat as add_maintainers: no attrname in vulnix' output, search by package
+            if self.pkg == pi.pkg {
+                self.programs.extend(all.programs_of(attr));
+            }
+        }
+        self
+    }
 }
 
 /// Information about a single package as expected in vulnix' JSON input
-#[derive(Debug, Default, Serialize)]
+#[derive(Debug, Clone, Default, Serialize)]
 struct InputPkg {
     name: Str,
     patches: Vec<String>,
@@ -139,6 +213,139 @@ impl InputPkgs {
     }
 }
 
+/// SRI-style integrity string (`sha256-<base64>`) over a package's canonical JSON encoding.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct Integrity(String);
+
+impl Integrity {
+    /// `vulnix_ver` is folded into the hash alongside the package itself: a newer vulnix may know
+    /// about advisories an older one didn't (see the doc comment on `vulnix_version`), so a
+    /// cached scan result must not survive a vulnix upgrade even if the package is unchanged.
+    fn of(pkg: &InputPkg, vulnix_ver: &str) -> Result<Self> {
+        let bytes =
+            serde_json::to_vec(&(pkg, vulnix_ver)).context("Cannot serialize package for hashing")?;
+        Ok(Self(format!("sha256-{}", base64::encode(Sha256::digest(&bytes)))))
+    }
+
+    /// Filesystem-safe form of the integrity string, used as the cache entry's file name.
+    fn filename(&self) -> String {
+        self.0.replace('/', "_")
+    }
+}
+
+/// One cached result, plus the integrity it was stored under so a read can detect a corrupted or
+/// truncated entry before trusting it.
+#[derive(Debug, Serialize, Deserialize)]
+struct CacheEntry {
+    integrity: Integrity,
+    /// `None` means the package was scanned and confirmed *not* vulnerable.
+    result: Option<VulnixRes>,
+}
+
+/// Content-addressed, per-package cache for vulnix scan results.
+///
+/// [`crate::cache::Cache`] caches an entire branch scan's result, keyed by revision, filter and
+/// vulnix version - a miss there re-scans every package. This caches at finer grain: each
+/// package's own scan result, addressed by an integrity hash over its serialized [`InputPkg`]
+/// (derivation name, patches, known vulnerabilities) plus the vulnix version. A package whose
+/// content hasn't changed - even across branches, or on a rerun after a transient failure - is
+/// served from here without spawning vulnix for it at all; only genuinely new/changed
+/// packages, or a vulnix upgrade invalidating the whole cache, go through vulnix.
+pub struct PkgCache {
+    dir: PathBuf,
+}
+
+impl PkgCache {
+    pub fn open(dir: &Path) -> Result<Self> {
+        fs::create_dir_all(dir).with_context(|| format!("Cannot create {:?}", dir))?;
+        Ok(Self { dir: dir.to_owned() })
+    }
+
+    fn path(&self, integrity: &Integrity) -> PathBuf {
+        self.dir.join(format!("{}.json", integrity.filename()))
+    }
+
+    /// Reads back a previously [`Self::put`] entry, verifying its integrity hash still matches
+    /// the key it's stored under. `None` means a cache miss; `Some(None)` means the package was
+    /// previously confirmed not vulnerable.
+    fn get(&self, integrity: &Integrity) -> Result<Option<Option<VulnixRes>>> {
+        let path = self.path(integrity);
+        let entry: CacheEntry = match File::open(&path) {
+            Ok(f) => serde_json::from_reader(BufReader::new(f))
+                .with_context(|| format!("Cannot decode cache entry {:?}", path))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => {
+                return Err(e).with_context(|| format!("Cannot read cache entry {:?}", path))
+            }
+        };
+        ensure!(
+            entry.integrity == *integrity,
+            "Cache entry {:?} failed integrity verification",
+            path
+        );
+        Ok(Some(entry.result))
+    }
+
+    /// Writes `result` (`None` for "confirmed not vulnerable") under `integrity`, via a temp
+    /// file + rename so a crash mid-write can never leave [`Self::get`] reading a half-written
+    /// entry.
+    fn put(&self, integrity: &Integrity, result: Option<&VulnixRes>) -> Result<()> {
+        let path = self.path(integrity);
+        let tmp = path.with_extension("tmp");
+        let entry = CacheEntry {
+            integrity: integrity.clone(),
+            result: result.cloned(),
+        };
+        {
+            let f =
+                File::create(&tmp).with_context(|| format!("Cannot write cache entry {:?}", tmp))?;
+            serde_json::to_writer(BufWriter::new(f), &entry).context("Cannot encode cache entry")?;
+        }
+        fs::rename(&tmp, &path).with_context(|| format!("Cannot write cache entry {:?}", path))
+    }
+
+    /// Splits `pkgs` into already-cached results and the attrs that still need a vulnix run,
+    /// alongside the integrity hash computed for every attr so [`Self::update`] can write fresh
+    /// results back under the same keys.
+    fn split(
+        &self,
+        pkgs: &InputPkgs,
+        vulnix_ver: &str,
+    ) -> Result<(Vec<VulnixRes>, InputPkgs, HashMap<Attr, Integrity>)> {
+        let mut hits = Vec::new();
+        let mut misses = HashMap::new();
+        let mut integrities = HashMap::with_capacity(pkgs.0.len());
+        for (attr, pkg) in &pkgs.0 {
+            let integrity = Integrity::of(pkg, vulnix_ver)?;
+            match self.get(&integrity)? {
+                Some(Some(res)) => hits.push(res),
+                Some(None) => {} // confirmed not vulnerable, nothing to carry over
+                None => {
+                    misses.insert(attr.clone(), pkg.clone());
+                }
+            }
+            integrities.insert(attr.clone(), integrity);
+        }
+        Ok((hits, InputPkgs(misses), integrities))
+    }
+
+    /// Records `fresh` vulnix results against the attrs in `scanned`, keyed by the integrity
+    /// hashes [`Self::split`] handed out for them. An attr with no matching result in `fresh` is
+    /// recorded as confirmed not vulnerable.
+    fn update(
+        &self,
+        scanned: &InputPkgs,
+        integrities: &HashMap<Attr, Integrity>,
+        fresh: &[VulnixRes],
+    ) -> Result<()> {
+        for (attr, pkg) in &scanned.0 {
+            let res = fresh.iter().find(|r| r.pkg.name == pkg.name);
+            self.put(&integrities[attr], res)?;
+        }
+        Ok(())
+    }
+}
+
 // === Tests ===
 
 #[cfg(test)]
@@ -149,6 +356,21 @@ mod test {
 
     use std::error::Error;
     use std::fs::read_to_string;
+    use std::str::FromStr;
+    use tempfile::TempDir;
+
+    fn input_pkgs(attr: &str, name: &str) -> InputPkgs {
+        let mut pkgs = HashMap::new();
+        pkgs.insert(
+            Attr::from(attr),
+            InputPkg {
+                name: name.into(),
+                patches: vec![],
+                known_vulnerabilities: vec![],
+            },
+        );
+        InputPkgs(pkgs)
+    }
 
     /// Standard `Opt` struct for testing purposes
     fn opt() -> Roundup {
@@ -203,4 +425,73 @@ mod test {
         assert_eq!(maintainer_contacts(&scan[2].maintainers), &["ericson2314"]);
         Ok(())
     }
+
+    #[test]
+    fn pkg_cache_serves_vulnerable_hit_from_cache() -> Result<(), Box<dyn Error>> {
+        let tmp = TempDir::new()?;
+        let cache = PkgCache::open(tmp.path())?;
+        let pkgs = input_pkgs("curl", "curl-7.80.0");
+        let (hits, misses, integrities) = cache.split(&pkgs, "1.0")?;
+        assert!(hits.is_empty());
+        assert_eq!(misses.0.len(), 1);
+        let fresh = vec![VulnixRes::new(
+            Package::from_str("curl-7.80.0")?,
+            vec![],
+        )];
+        cache.update(&misses, &integrities, &fresh)?;
+        let (hits, misses, _) = cache.split(&pkgs, "1.0")?;
+        assert_eq!(hits, fresh);
+        assert!(misses.0.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn pkg_cache_remembers_confirmed_safe_packages() -> Result<(), Box<dyn Error>> {
+        let tmp = TempDir::new()?;
+        let cache = PkgCache::open(tmp.path())?;
+        let pkgs = input_pkgs("hello", "hello-2.10");
+        let (_, misses, integrities) = cache.split(&pkgs, "1.0")?;
+        cache.update(&misses, &integrities, &[])?;
+        let (hits, misses, _) = cache.split(&pkgs, "1.0")?;
+        assert!(hits.is_empty());
+        assert!(misses.0.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn pkg_cache_rejects_tampered_entry() -> Result<(), Box<dyn Error>> {
+        let tmp = TempDir::new()?;
+        let cache = PkgCache::open(tmp.path())?;
+        let pkgs = input_pkgs("curl", "curl-7.80.0");
+        let (_, misses, integrities) = cache.split(&pkgs, "1.0")?;
+        let fresh = vec![VulnixRes::new(
+            Package::from_str("curl-7.80.0")?,
+            vec![],
+        )];
+        cache.update(&misses, &integrities, &fresh)?;
+        let integrity = integrities.values().next().unwrap().clone();
+        let path = cache.path(&integrity);
+        let mut tampered: CacheEntry = serde_json::from_reader(File::open(&path)?)?;
+        tampered.integrity = Integrity("sha256-bogus".to_owned());
+        serde_json::to_writer(File::create(&path)?, &tampered)?;
+        assert!(cache.split(&pkgs, "1.0").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn pkg_cache_invalidates_on_vulnix_upgrade() -> Result<(), Box<dyn Error>> {
+        let tmp = TempDir::new()?;
+        let cache = PkgCache::open(tmp.path())?;
+        let pkgs = input_pkgs("curl", "curl-7.80.0");
+        let (_, misses, integrities) = cache.split(&pkgs, "1.0")?;
+        let fresh = vec![VulnixRes::new(Package::from_str("curl-7.80.0")?, vec![])];
+        cache.update(&misses, &integrities, &fresh)?;
+        let (hits, misses, _) = cache.split(&pkgs, "1.0")?;
+        assert_eq!(hits, fresh, "unchanged package + vulnix version should be a cache hit");
+        assert!(misses.0.is_empty());
+        let (hits, misses, _) = cache.split(&pkgs, "2.0")?;
+        assert!(hits.is_empty(), "a vulnix upgrade must invalidate the cached result");
+        assert_eq!(misses.0.len(), 1);
+        Ok(())
+    }
 }
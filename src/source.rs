@@ -7,13 +7,14 @@ use anyhow::{ensure, Context, Result};
 use colored::*;
 use lazy_static::lazy_static;
 use regex::Regex;
+use rusqlite::Connection;
 use serde::{Deserialize, Serialize};
 use smol_str::SmolStr;
-use std::cmp::PartialEq;
+use std::cmp::{Ordering, PartialEq};
 use std::collections::HashMap;
 use std::convert::TryFrom;
 use std::fmt;
-use std::fs::File;
+use std::fs::{self, File};
 use std::io::{BufReader, BufWriter, Write};
 use std::ops::{Deref, DerefMut};
 use std::path::{Path, PathBuf};
@@ -67,7 +68,7 @@ pub fn maintainer_contacts(maint: &[Maintainer]) -> Vec<&Str> {
 
 /// packages.json data structure as emitted by `nix-env -qa --json`. Some unimportant fields
 /// omitted.
-#[derive(Debug, Clone, Default, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct NixEnvPkg {
     #[serde(rename = "name")]
     pub pkg: Str,
@@ -76,7 +77,7 @@ pub struct NixEnvPkg {
 }
 
 /// Metadata section in packages.json output. We include only the interesting fields here.
-#[derive(Debug, Clone, Default, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct PkgMeta {
     #[serde(default)]
     pub available: bool,
@@ -91,7 +92,7 @@ pub struct PkgMeta {
 /// Nix attribute name. Can also be a dotted expression like pythonPackages.docutils
 pub type Attr = Str;
 
-#[derive(Debug, Default, Deserialize)]
+#[derive(Debug, Default, Serialize, Deserialize)]
 pub struct Patches(HashMap<Attr, Vec<String>>);
 
 impl Deref for Patches {
@@ -108,17 +109,93 @@ impl DerefMut for Patches {
     }
 }
 
+/// Maps executable names to the nixpkgs attributes that provide them (and back), as shipped in a
+/// channel's `programs.sqlite` (table `Programs(name, system, package)`). Both directions are
+/// built once from a single pass over the table, since the file is large and this index tends to
+/// be queried once per package/program in a scan.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ProgramIndex {
+    by_name: HashMap<Str, Vec<Attr>>,
+    by_package: HashMap<Attr, Vec<Str>>,
+}
+
+impl ProgramIndex {
+    /// Loads the index from `path`, keeping only entries for [`SYSTEM`].
+    pub fn open(path: &Path) -> Result<Self> {
+        let conn = Connection::open(path)
+            .with_context(|| format!("Cannot open program database {:?}", path))?;
+        let mut stmt = conn.prepare("SELECT name, package FROM Programs WHERE system = ?1")?;
+        let mut idx = Self::default();
+        let rows = stmt.query_map([SYSTEM], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+        })?;
+        for row in rows {
+            let (name, package) = row?;
+            let (name, package) = (Str::from(name), Attr::from(package));
+            idx.by_name.entry(name.clone()).or_default().push(package.clone());
+            idx.by_package.entry(package).or_default().push(name);
+        }
+        Ok(idx)
+    }
+
+    /// Executables that `attr` provides, as typed on a shell (e.g. `curl`, `curl-config`).
+    fn programs_of(&self, attr: &str) -> Vec<Str> {
+        self.by_package.get(attr).cloned().unwrap_or_default()
+    }
+}
+
 /// List of all available packages.
-#[derive(Debug, Clone, Default, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct AllPackages {
     pub packages: HashMap<Attr, NixEnvPkg>,
+    #[serde(default)]
+    programs: ProgramIndex,
+}
+
+/// Current commit id of the nixpkgs checkout in `workdir`. Used as the cache key for
+/// [`AllPackages::query`]/[`AllPackages::discover_patches`]: as long as it hasn't moved, neither
+/// has changed.
+fn current_rev(workdir: &Path) -> Result<String> {
+    let out = Command::new("git")
+        .args(&["rev-parse", "HEAD"])
+        .current_dir(workdir)
+        .output()
+        .context("Cannot exec git rev-parse")?;
+    ensure!(
+        out.status.success(),
+        "git rev-parse HEAD failed: {}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    Ok(String::from_utf8(out.stdout)?.trim().to_owned())
 }
 
 impl AllPackages {
-    /// Gets comprehensive list of packages by running `nix-env -qa --json`.
+    /// Gets comprehensive list of packages by running `nix-env -qa --json`, or, if the nixpkgs
+    /// checkout's commit id hasn't changed since the last run, the retained result from
+    /// `cache_dir`.
     ///
     /// - workdir: nixpkgs dir with checked out branch
-    pub fn query(workdir: &Path) -> Result<Self> {
+    /// - programs_db: explicit path to a `programs.sqlite`, overriding the copy auto-detected
+    ///   alongside the queried package list (if any). The feature degrades gracefully if neither
+    ///   is present: [`Self::programs_of`] just returns nothing.
+    pub fn query(workdir: &Path, cache_dir: &Path, programs_db: Option<&Path>) -> Result<Self> {
+        let rev = current_rev(workdir)?;
+        let cache_file = cache_dir.join(format!("{}.packages.bincode", rev));
+        if let Some(mut cached) = fs::read(&cache_file)
+            .ok()
+            .and_then(|b| bincode::deserialize::<Self>(&b).ok())
+        {
+            info!(
+                "Using cached package list for {} ({})",
+                workdir.to_string_lossy().green(),
+                &rev[..11.min(rev.len())]
+            );
+            if let Some(db) = programs_db.filter(|db| db.exists()) {
+                cached.programs = ProgramIndex::open(db)
+                    .with_context(|| format!("Cannot load {:?}", db))?;
+            }
+            return Ok(cached);
+        }
         info!(
             "Querying all packages in {}",
             workdir.to_string_lossy().green()
@@ -150,11 +227,51 @@ impl AllPackages {
         res.packages.retain(|_, v| {
             v.meta.available && v.system == SYSTEM && Package::from_str(&v.pkg).is_ok()
         });
+        let programs_sqlite = programs_db
+            .map(Path::to_path_buf)
+            .unwrap_or_else(|| packages_json.with_file_name("programs.sqlite"));
+        if programs_sqlite.exists() {
+            res.programs = ProgramIndex::open(&programs_sqlite)
+                .with_context(|| format!("Cannot load {:?}", programs_sqlite))?;
+        }
+        fs::create_dir_all(cache_dir).ok();
+        if let Ok(bytes) = bincode::serialize(&res) {
+            fs::write(&cache_file, bytes)
+                .with_context(|| format!("Cannot write package cache to {:?}", cache_file))?;
+        }
         Ok(res)
     }
 
-    /// Instantiates all derivation paths (.drv files) and return applied patches
-    pub fn discover_patches(&self, workdir: &Path) -> Result<Patches> {
+    /// Resolves a bare executable name (as it would be typed on a shell, not a nixpkgs attribute)
+    /// to the packages that provide it, via the `programs.sqlite` index loaded in [`Self::query`].
+    pub fn resolve_program(&self, bin: &str) -> Vec<&NixEnvPkg> {
+        self.programs
+            .by_name
+            .get(bin)
+            .into_iter()
+            .flatten()
+            .filter_map(|attr| self.packages.get(attr))
+            .collect()
+    }
+
+    /// Executables that the package at `attr` provides, via the `programs.sqlite` index loaded in
+    /// [`Self::query`]. Empty if the index wasn't available or `attr` ships no executables.
+    pub fn programs_of(&self, attr: &str) -> Vec<Str> {
+        self.programs.programs_of(attr)
+    }
+
+    /// Instantiates all derivation paths (.drv files) and return applied patches, or the cached
+    /// result from `cache_dir` if the nixpkgs checkout's commit id hasn't changed.
+    pub fn discover_patches(&self, workdir: &Path, cache_dir: &Path) -> Result<Patches> {
+        let rev = current_rev(workdir)?;
+        let cache_file = cache_dir.join(format!("{}.patches.bincode", rev));
+        if let Some(cached) = fs::read(&cache_file)
+            .ok()
+            .and_then(|b| bincode::deserialize::<Patches>(&b).ok())
+        {
+            info!("Using cached patch list for {}", &rev[..11.min(rev.len())]);
+            return Ok(cached);
+        }
         let todo: Vec<_> = self.packages.keys().collect();
         let (f, tmp) = NamedTempFile::new()?.into_parts();
         {
@@ -190,12 +307,18 @@ impl AllPackages {
             tmp.keep()?.display(),
             String::from_utf8_lossy(&out.stderr)
         );
-        serde_json::from_slice(&out.stdout).with_context(|| {
+        let patches: Patches = serde_json::from_slice(&out.stdout).with_context(|| {
             format!(
                 "Failed to parse patch list: {}",
                 String::from_utf8_lossy(&out.stdout)
             )
-        })
+        })?;
+        fs::create_dir_all(cache_dir).ok();
+        if let Ok(bytes) = bincode::serialize(&patches) {
+            fs::write(&cache_file, bytes)
+                .with_context(|| format!("Cannot write patch cache to {:?}", cache_file))?;
+        }
+        Ok(patches)
     }
 
     pub fn retain<F>(&mut self, mut f: F)
@@ -242,6 +365,79 @@ impl Package {
     pub fn version(&self) -> &str {
         &self.name[self.v_idx..]
     }
+
+    /// Compares this package's version against `other` the way Nix's `builtins.compareVersions`
+    /// would, so callers can tell whether a branch already carries a fix without string equality.
+    pub fn version_cmp(&self, other: &str) -> Ordering {
+        compare_versions(self.version(), other)
+    }
+}
+
+/// Splits a version string into Nix-style components: maximal runs of ASCII digits, or maximal
+/// runs of everything else except `.`/`-`, which are separators and are skipped between
+/// components (a digit/non-digit transition also ends a component, even without a separator).
+fn version_components(s: &str) -> Vec<&str> {
+    let b = s.as_bytes();
+    let mut components = Vec::new();
+    let mut i = 0;
+    while i < b.len() {
+        if b[i] == b'.' || b[i] == b'-' {
+            i += 1;
+            continue;
+        }
+        let start = i;
+        if b[i].is_ascii_digit() {
+            while i < b.len() && b[i].is_ascii_digit() {
+                i += 1;
+            }
+        } else {
+            while i < b.len() && b[i] != b'.' && b[i] != b'-' && !b[i].is_ascii_digit() {
+                i += 1;
+            }
+        }
+        components.push(&s[start..i]);
+    }
+    components
+}
+
+fn compare_component(a: Option<&str>, b: Option<&str>) -> Ordering {
+    match (a, b) {
+        (None, None) => Ordering::Equal,
+        (Some("pre"), Some("pre")) => Ordering::Equal,
+        (Some("pre"), _) => Ordering::Less,
+        (_, Some("pre")) => Ordering::Greater,
+        (None, Some(_)) => Ordering::Less,
+        (Some(_), None) => Ordering::Greater,
+        (Some(x), Some(y)) => {
+            let is_num = |s: &str| !s.is_empty() && s.bytes().all(|c| c.is_ascii_digit());
+            match (is_num(x), is_num(y)) {
+                (true, true) => {
+                    let trim = |s: &str| s.trim_start_matches('0');
+                    match trim(x).len().cmp(&trim(y).len()) {
+                        Ordering::Equal => trim(x).cmp(trim(y)),
+                        ord => ord,
+                    }
+                }
+                (true, false) => Ordering::Greater,
+                (false, true) => Ordering::Less,
+                (false, false) => x.cmp(y),
+            }
+        }
+    }
+}
+
+/// Nix-accurate `compareVersions`: splits both version strings into components (see
+/// [`version_components`]) and compares them pairwise (see [`compare_component`]) until one side
+/// runs out.
+pub fn compare_versions(a: &str, b: &str) -> Ordering {
+    let (ca, cb) = (version_components(a), version_components(b));
+    for i in 0..ca.len().max(cb.len()) {
+        match compare_component(ca.get(i).copied(), cb.get(i).copied()) {
+            Ordering::Equal => continue,
+            ord => return ord,
+        }
+    }
+    Ordering::Equal
 }
 
 impl fmt::Display for Package {
@@ -307,6 +503,7 @@ impl TryFrom<String> for Package {
 #[cfg(test)]
 mod test {
     use super::*;
+    use maplit::hashmap;
     use serde_json::json;
 
     #[test]
@@ -316,6 +513,48 @@ mod test {
         assert_eq!("1.0.2d", p.version());
     }
 
+    #[test]
+    fn version_cmp_prerelease() {
+        assert_eq!(
+            Package::new("foo", "1.0").version_cmp("1.0pre1"),
+            Ordering::Greater
+        );
+        assert_eq!(
+            Package::new("foo", "1.0pre1").version_cmp("1.0"),
+            Ordering::Less
+        );
+    }
+
+    #[test]
+    fn version_cmp_extra_suffix_is_greater() {
+        assert_eq!(
+            Package::new("foo", "2.3").version_cmp("2.3a"),
+            Ordering::Less
+        );
+        assert_eq!(
+            Package::new("foo", "2.3a").version_cmp("2.3"),
+            Ordering::Greater
+        );
+    }
+
+    #[test]
+    fn version_cmp_numeric_beats_alphabetic() {
+        assert_eq!(compare_versions("2.3a", "2.3.1"), Ordering::Less);
+        assert_eq!(compare_versions("2.3.1", "2.3a"), Ordering::Greater);
+    }
+
+    #[test]
+    fn version_cmp_ignores_leading_zeros() {
+        assert_eq!(compare_versions("1.01", "1.1"), Ordering::Equal);
+        assert_eq!(compare_versions("1.9", "1.10"), Ordering::Less);
+    }
+
+    #[test]
+    fn version_cmp_equal_and_lexical_tail() {
+        assert_eq!(compare_versions("1.0", "1.0"), Ordering::Equal);
+        assert_eq!(compare_versions("1.0a", "1.0b"), Ordering::Less);
+    }
+
     #[test]
     fn format() {
         let p = Package::new("binutils", "2.32.1");
@@ -444,4 +683,57 @@ mod test {
             vec!["CVE-2017-9051", "CVE-2018-5684"]
         );
     }
+
+    #[test]
+    fn resolve_program_via_sqlite() {
+        let tmp = tempfile::Builder::new().suffix(".sqlite").tempfile().unwrap();
+        let conn = Connection::open(tmp.path()).unwrap();
+        conn.execute(
+            "CREATE TABLE Programs (name TEXT, system TEXT, package TEXT)",
+            [],
+        )
+        .unwrap();
+        conn.execute(
+            "INSERT INTO Programs VALUES ('curl', 'x86_64-linux', 'curl'), \
+             ('curl', 'aarch64-linux', 'curlAarch64')",
+            [],
+        )
+        .unwrap();
+        let mut all = AllPackages::default();
+        all.packages.insert(
+            "curl".into(),
+            NixEnvPkg {
+                pkg: "curl-7.80.0".into(),
+                system: SYSTEM.into(),
+                ..NixEnvPkg::default()
+            },
+        );
+        all.programs = ProgramIndex::open(tmp.path()).unwrap();
+        let found = all.resolve_program("curl");
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].pkg, "curl-7.80.0");
+        assert!(all.resolve_program("nonexistent").is_empty());
+        assert_eq!(all.programs_of("curl"), vec![Str::from("curl")]);
+        assert!(all.programs_of("nonexistent").is_empty());
+    }
+
+    #[test]
+    fn bincode_roundtrip_preserves_programs() {
+        let mut all = AllPackages::default();
+        all.packages.insert(
+            "curl".into(),
+            NixEnvPkg {
+                pkg: "curl-7.80.0".into(),
+                ..NixEnvPkg::default()
+            },
+        );
+        all.programs = ProgramIndex {
+            by_name: hashmap! { "curl".into() => vec!["curl".into()] },
+            by_package: hashmap! { "curl".into() => vec!["curl".into()] },
+        };
+        let bytes = bincode::serialize(&all).unwrap();
+        let back: AllPackages = bincode::deserialize(&bytes).unwrap();
+        assert_eq!(back.resolve_program("curl").len(), 1);
+        assert_eq!(back.programs_of("curl"), vec![Str::from("curl")]);
+    }
 }
@@ -3,21 +3,29 @@ use crate::branches::{Branch, ScanByBranch};
 use crate::scan::ScoreMap;
 use crate::source::{maintainer_contacts, Maintainer, Package};
 
+use anyhow::{Context, Result};
 use ordered_float::OrderedFloat;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use std::cmp::Ordering;
 use std::collections::HashMap;
 use std::fmt;
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::Path;
 
 /// Abstract ticket/issue representation.
 ///
 /// This will be picked up by tracker/* to create a concrete issue.
-#[derive(Debug, Clone, PartialEq, Default, Serialize)]
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
 pub struct Ticket {
     pub iteration: u32,
     pub pkg: Package,
     pub affected: HashMap<Advisory, Detail>,
     pub maintainers: Vec<Maintainer>,
+    /// Executables this package provides (e.g. `curl`, `curl-config`), so maintainers can see at
+    /// a glance which commands are impacted. Empty if `programs.sqlite` wasn't available.
+    #[serde(default)]
+    pub programs: Vec<String>,
 }
 
 impl Ticket {
@@ -68,13 +76,17 @@ impl Ticket {
         ",
             pname = self.pname()
         )?;
+        if !self.programs.is_empty() {
+            writeln!(f, "Affects: {}\n", self.programs.join(", "))?;
+        }
         let mut adv: Vec<(&Advisory, &Detail)> = self.affected.iter().collect();
         adv.sort_unstable_by(cmp_score);
         for (advisory, detail) in &adv {
             writeln!(
                 f,
-                "* [ ] [{adv}](https://nvd.nist.gov/vuln/detail/{adv}) {detail}",
+                "* [ ] [{adv}]({url}) {detail}",
                 adv = advisory,
+                url = advisory.url(),
                 detail = detail
             )?;
         }
@@ -117,7 +129,7 @@ impl fmt::Display for Ticket {
     }
 }
 
-#[derive(Debug, Clone, Default, PartialEq, PartialOrd, Serialize)]
+#[derive(Debug, Clone, Default, PartialEq, PartialOrd, Serialize, Deserialize)]
 pub struct Detail {
     branches: Vec<Branch>,
     score: Option<OrderedFloat<f32>>,
@@ -125,7 +137,7 @@ pub struct Detail {
 }
 
 impl Detail {
-    fn new(score: Option<f32>, description: Option<String>) -> Self {
+    pub fn new(score: Option<f32>, description: Option<String>) -> Self {
         Self {
             score: score.map(OrderedFloat),
             description,
@@ -136,6 +148,11 @@ impl Detail {
     fn add(&mut self, branch: Branch) {
         self.branches.push(branch);
     }
+
+    /// CVSS score, if known. Used by `count::diff_report` to detect rescored advisories.
+    pub fn score(&self) -> Option<f32> {
+        self.score.map(OrderedFloat::into_inner)
+    }
 }
 
 impl fmt::Display for Detail {
@@ -163,6 +180,7 @@ pub fn ticket_list(iteration: u32, scan_res: ScanByBranch) -> Vec<Ticket> {
     let mut scores = ScoreMap::default();
     // Maintainership may change across branches. Collect & notify all maintainers.
     let mut maintmap: HashMap<Package, Vec<Maintainer>> = HashMap::new();
+    let mut progmap: HashMap<Package, Vec<String>> = HashMap::new();
     let mut descmap: HashMap<Advisory, String> = HashMap::new();
     // Step 1: for each pkg, record all pairs (advisory, branch)
     let mut pkgmap: HashMap<Package, Vec<(Advisory, Branch)>> = HashMap::new();
@@ -170,6 +188,8 @@ pub fn ticket_list(iteration: u32, scan_res: ScanByBranch) -> Vec<Ticket> {
         for res in vulnix_res {
             let m = maintmap.entry(res.pkg.clone()).or_insert_with(Vec::new);
             m.extend(res.maintainers);
+            let p = progmap.entry(res.pkg.clone()).or_insert_with(Vec::new);
+            p.extend(res.programs.iter().map(ToString::to_string));
             let p = pkgmap.entry(res.pkg).or_insert_with(Vec::new);
             p.extend(res.affected_by.into_iter().map(|adv| (adv, branch.clone())));
             scores.extend(res.cvssv3_basescore);
@@ -195,6 +215,11 @@ pub fn ticket_list(iteration: u32, scan_res: ScanByBranch) -> Vec<Ticket> {
                 t.maintainers.sort();
                 t.maintainers.dedup();
             }
+            if let Some(programs) = progmap.remove(&t.pkg) {
+                t.programs = programs;
+                t.programs.sort();
+                t.programs.dedup();
+            }
             t
         })
         .collect();
@@ -202,6 +227,26 @@ pub fn ticket_list(iteration: u32, scan_res: ScanByBranch) -> Vec<Ticket> {
     tickets
 }
 
+/// File name under which an iteration's full ticket list is persisted so a later iteration can
+/// diff against it (see `count::diff_report`).
+const TICKETS_JSON: &str = "tickets.json";
+
+/// Persists `tickets` as `tickets.json` in the iteration dir `dir`.
+pub fn save(tickets: &[Ticket], dir: &Path) -> Result<()> {
+    let path = dir.join(TICKETS_JSON);
+    serde_json::to_writer_pretty(BufWriter::new(File::create(&path)?), tickets)
+        .with_context(|| format!("Cannot write ticket list to {:?}", path))
+}
+
+/// Loads a previously [`save`]d ticket list from the iteration dir `dir`.
+pub fn load(dir: &Path) -> Result<Vec<Ticket>> {
+    let path = dir.join(TICKETS_JSON);
+    serde_json::from_reader(BufReader::new(
+        File::open(&path).with_context(|| format!("Cannot open ticket list {:?}", path))?,
+    ))
+    .with_context(|| format!("Cannot parse ticket list {:?}", path))
+}
+
 // === Tests ===
 
 #[cfg(test)]
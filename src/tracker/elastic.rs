@@ -0,0 +1,301 @@
+//! Elasticsearch-backed tracker.
+//!
+//! Indexes every generated [`Ticket`] into an Elasticsearch cluster instead of filing GitHub
+//! issues, so the full corpus of vulnerability roundups becomes full-text
+//! searchable by CVE, package, branch, and maintainer. This mirrors how the NixOS search importer
+//! ingests channel data into Elasticsearch, applied to advisory tickets.
+
+use super::{Issue, Tracker};
+use crate::source::maintainer_contacts;
+use crate::ticket::Ticket;
+
+use colored::*;
+use reqwest::blocking::Client;
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use smol_str::SmolStr;
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("HTTP request error")]
+    Request(#[from] reqwest::Error),
+    #[error("Elasticsearch bulk index request reported errors: {0}")]
+    Bulk(String),
+    #[error("Cannot serialize ticket for indexing")]
+    Encode(#[from] serde_json::Error),
+    #[error("Invalid exists-strategy '{0}', expected 'abort' or 'recreate'")]
+    Strategy(String),
+}
+
+type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// Environment variable holding the cluster base URL, e.g. `https://es.example.org:9200`.
+pub const URL_VAR: &str = "ELASTICSEARCH_URL";
+
+/// What to do if the target index already exists when [`Elastic::new`] runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExistsStrategy {
+    /// Keep the existing index (and whatever tickets it already holds) and just append to it.
+    /// The safe default for a recurring survey, which relies on the index accumulating history.
+    Abort,
+    /// Delete and recreate the index, discarding previously indexed tickets and rebuilding its
+    /// mapping from scratch.
+    Recreate,
+}
+
+impl FromStr for ExistsStrategy {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "abort" => Ok(Self::Abort),
+            "recreate" => Ok(Self::Recreate),
+            _ => Err(Error::Strategy(s.to_owned())),
+        }
+    }
+}
+
+/// Document shape stored for each ticket: the ticket itself, plus a rendered title/body so the
+/// index is full-text searchable the same way a GitHub issue would be.
+#[derive(Debug, Serialize)]
+struct TicketDoc<'a> {
+    #[serde(flatten)]
+    ticket: &'a Ticket,
+    pname: &'a str,
+    maintainers: Vec<&'a SmolStr>,
+    title: String,
+    body: String,
+}
+
+impl<'a> TicketDoc<'a> {
+    fn new(ticket: &'a Ticket) -> Self {
+        let mut body = String::with_capacity(4096);
+        ticket.render(&mut body, false).ok();
+        Self {
+            ticket,
+            pname: ticket.pname(),
+            maintainers: maintainer_contacts(&ticket.maintainers),
+            title: ticket.summary(),
+            body,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Default)]
+struct BulkResponse {
+    errors: bool,
+    #[serde(default)]
+    items: Vec<Value>,
+}
+
+#[derive(Debug, Deserialize, Default)]
+struct SearchResponse {
+    hits: SearchHits,
+}
+
+#[derive(Debug, Deserialize, Default)]
+struct SearchHits {
+    #[serde(default)]
+    hits: Vec<SearchHit>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SearchHit {
+    #[serde(rename = "_id")]
+    id: String,
+    #[serde(rename = "_source")]
+    source: Value,
+}
+
+#[derive(Debug, Clone)]
+pub struct Elastic {
+    client: Client,
+    base_url: String,
+    index: String,
+}
+
+impl Elastic {
+    pub fn new(base_url: &str, index: &str, on_exists: ExistsStrategy) -> Result<Self> {
+        let client = Client::builder()
+            .user_agent(format!(
+                "{}/{}",
+                clap::crate_name!(),
+                clap::crate_version!()
+            ))
+            .build()?;
+        let es = Self {
+            client,
+            base_url: base_url.trim_end_matches('/').to_owned(),
+            index: index.to_owned(),
+        };
+        es.ensure_index(on_exists)?;
+        Ok(es)
+    }
+
+    fn index_url(&self) -> String {
+        format!("{}/{}", self.base_url, self.index)
+    }
+
+    fn doc_url(&self, id: &str) -> String {
+        format!("{}/_doc/{}", self.index_url(), id)
+    }
+
+    fn ensure_index(&self, on_exists: ExistsStrategy) -> Result<()> {
+        let exists = self.client.head(&self.index_url()).send()?.status() == StatusCode::OK;
+        match (exists, on_exists) {
+            (false, _) => {
+                self.client
+                    .put(&self.index_url())
+                    .send()?
+                    .error_for_status()?;
+            }
+            (true, ExistsStrategy::Recreate) => {
+                self.client
+                    .delete(&self.index_url())
+                    .send()?
+                    .error_for_status()?;
+                self.client
+                    .put(&self.index_url())
+                    .send()?
+                    .error_for_status()?;
+            }
+            (true, ExistsStrategy::Abort) => {
+                debug!(
+                    "Elasticsearch index {} already exists, appending to it",
+                    self.index.green()
+                );
+            }
+        }
+        Ok(())
+    }
+
+    fn doc_id(tkt: &Ticket) -> String {
+        format!("{}-{}", tkt.iteration, tkt.name())
+    }
+
+    fn bulk_index(&self, tickets: &[Ticket]) -> Result<()> {
+        let mut body = String::new();
+        for tkt in tickets {
+            let id = Self::doc_id(tkt);
+            let action = json!({"index": {"_index": self.index, "_id": id}});
+            body.push_str(&action.to_string());
+            body.push('\n');
+            body.push_str(&serde_json::to_string(&TicketDoc::new(tkt))?);
+            body.push('\n');
+        }
+        let res: BulkResponse = self
+            .client
+            .post(&format!("{}/_bulk", self.base_url))
+            .header("Content-Type", "application/x-ndjson")
+            .body(body)
+            .send()?
+            .error_for_status()?
+            .json()?;
+        if res.errors {
+            return Err(Error::Bulk(serde_json::to_string(&res.items).unwrap_or_default()));
+        }
+        for tkt in tickets {
+            info!(
+                "{}: indexed into {}",
+                tkt.name(),
+                self.doc_url(&Self::doc_id(tkt)).purple()
+            );
+        }
+        Ok(())
+    }
+
+    fn search_all(&self) -> Result<Vec<Issue>> {
+        let res: SearchResponse = self
+            .client
+            .post(&format!("{}/_search", self.index_url()))
+            .json(&json!({"query": {"match_all": {}}, "size": 1000}))
+            .send()?
+            .error_for_status()?
+            .json()?;
+        Ok(res
+            .hits
+            .hits
+            .into_iter()
+            .map(|h| issue_of(&h.id, &h.source))
+            .collect())
+    }
+
+    fn update(&self, issue: &Issue, body: &str) -> Result<()> {
+        self.client
+            .post(&format!("{}/_update", self.doc_url(&issue.url)))
+            .json(&json!({"doc": {"body": body}}))
+            .send()?
+            .error_for_status()?;
+        Ok(())
+    }
+}
+
+/// Builds an [`Issue`] from a search hit. `url` carries the raw document id (not a fetchable
+/// URL) since that's all [`Elastic::update_issue`] needs to address the document again.
+fn issue_of(id: &str, source: &Value) -> Issue {
+    Issue {
+        id: 0,
+        url: id.to_owned(),
+        html_url: String::new(),
+        number: 0,
+        title: source["title"].as_str().unwrap_or_default().to_owned(),
+        body: source["body"].as_str().unwrap_or_default().to_owned(),
+    }
+}
+
+impl Tracker for Elastic {
+    fn create_issues(&self, tickets: Vec<Ticket>, _iterdir: &Path) -> Result<(), super::Error> {
+        if tickets.is_empty() {
+            return Ok(());
+        }
+        self.bulk_index(&tickets)?;
+        Ok(())
+    }
+
+    fn search(&self) -> Result<Vec<Issue>, super::Error> {
+        Ok(self.search_all()?)
+    }
+
+    fn update_issue(&self, issue: &Issue, body: &str) -> Result<(), super::Error> {
+        self.update(issue, body)?;
+        Ok(())
+    }
+
+    fn name(&self) -> String {
+        format!("Elasticsearch[{}]", self.index)
+    }
+
+    /// `search_all` returns the full historical corpus with no open/closed concept, and every
+    /// iteration's documents are addressed by a fresh `{iteration}-{name}` id (see
+    /// [`Self::doc_id`]). Reconciling against it would just keep patching the very first
+    /// iteration's document instead of indexing a new one each time, so skip it.
+    fn reconciles(&self) -> bool {
+        false
+    }
+}
+
+impl fmt::Display for Elastic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.index_url())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn exists_strategy_parse() {
+        assert_eq!(ExistsStrategy::from_str("abort").unwrap(), ExistsStrategy::Abort);
+        assert_eq!(
+            ExistsStrategy::from_str("recreate").unwrap(),
+            ExistsStrategy::Recreate
+        );
+        assert!(ExistsStrategy::from_str("bogus").is_err());
+    }
+}
@@ -48,6 +48,11 @@ impl Tracker for File {
         Ok(Vec::new())
     }
 
+    // not supported: search() never returns anything to reconcile against
+    fn update_issue(&self, _issue: &Issue, _body: &str) -> Result<(), super::Error> {
+        Ok(())
+    }
+
     fn name(&self) -> String {
         "File".into()
     }
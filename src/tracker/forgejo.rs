@@ -0,0 +1,230 @@
+//! Forgejo/Gitea-backed tracker.
+//!
+//! Much of the Nix ecosystem runs its own Forgejo or Gitea instance rather than GitHub, so this
+//! files issues against the Forgejo REST API instead. The API shape is close enough to GitHub's
+//! that the module follows `github.rs`'s create/comment/search flow, without its rate-limit retry
+//! handling. Self-hosted instances aren't on a fixed domain, so the API base URL has to be
+//! supplied rather than hardcoded.
+
+use super::{json_file, Issue, RepoSpec, SavedIssue, Tracker};
+use crate::ticket::Ticket;
+
+use clap::{crate_name, crate_version};
+use colored::*;
+use reqwest::blocking::Client;
+use reqwest::header::*;
+use serde::Deserialize;
+use serde_json::json;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+/// Backend tag `json_file`/[`SavedIssue::write`] persist under, e.g. `forgejo.<name>.json`.
+const BACKEND: &str = "forgejo";
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("Invalid Forgejo Api response at {url}: {resp}")]
+    Api {
+        url: String,
+        resp: String,
+        #[source]
+        e: serde_json::Error,
+    },
+    #[error("HTTP request error")]
+    Request(#[from] reqwest::Error),
+    #[error(transparent)]
+    RepoFormat(#[from] super::RepoFormatError),
+    #[error("Trying to construct invalid HTTP header")]
+    Header(#[from] http::header::InvalidHeaderValue),
+    #[error("Cannot write issue file '{}'", 0)]
+    Json(PathBuf, #[source] std::io::Error),
+}
+
+/// Shortcut
+fn api_err(url: &str, resp: String, e: serde_json::Error) -> Error {
+    Error::Api {
+        url: url.to_string(),
+        resp,
+        e,
+    }
+}
+
+type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// Environment variable holding the Forgejo/Gitea instance base URL, e.g.
+/// `https://forgejo.example.org`.
+pub const URL_VAR: &str = "FORGEJO_URL";
+
+const LABEL: &str = "1.severity: security";
+
+/// Forgejo/Gitea response to comment creation
+#[derive(Deserialize, Debug, Clone)]
+#[allow(unused)]
+struct Comment {
+    id: u64,
+    url: String,
+    html_url: String,
+}
+
+#[derive(Debug, Clone)]
+struct UrlFor {
+    issues: String,
+}
+
+impl UrlFor {
+    fn new(base_url: &str, repo: &RepoSpec) -> Self {
+        Self {
+            issues: format!(
+                "{}/api/v1/repos/{}/issues",
+                base_url.trim_end_matches('/'),
+                repo
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Forgejo {
+    client: Client,
+    repo: RepoSpec,
+    notify: bool,
+    url_for: UrlFor,
+}
+
+impl Forgejo {
+    pub fn new(base_url: &str, token: String, repo_spec: &str, notify: bool) -> Result<Self> {
+        let repo = repo_spec.parse()?;
+        let mut h = HeaderMap::new();
+        h.insert(AUTHORIZATION, format!("token {}", token).parse()?);
+        h.insert(ACCEPT, "application/json".parse()?);
+        h.insert(
+            USER_AGENT,
+            format!("{}/{}", crate_name!(), crate_version!()).parse()?,
+        );
+        let client = Client::builder().default_headers(h).build()?;
+        let url_for = UrlFor::new(base_url, &repo);
+        Ok(Self {
+            client,
+            repo,
+            notify,
+            url_for,
+        })
+    }
+
+    fn create(&self, tkt: &Ticket) -> Result<Issue> {
+        let url = &self.url_for.issues;
+        let mut body = String::with_capacity(4096);
+        tkt.render(&mut body, self.notify).ok();
+        let res = self
+            .client
+            .post(url)
+            .json(&json!({
+                "title": tkt.summary(),
+                "body": body,
+                "labels": vec![LABEL]
+            }))
+            .send()?;
+        let txt = res.text()?;
+        serde_json::from_str(&txt).map_err(|e| api_err(url, txt, e))
+    }
+
+    fn issues_query(&self, q: &str, page: usize) -> Result<Vec<Issue>> {
+        let url = &self.url_for.issues;
+        let res = self
+            .client
+            .get(url)
+            .query(&[
+                ("state", "open"),
+                ("type", "issues"),
+                ("labels", LABEL),
+                ("q", q),
+                ("page", &page.to_string()),
+            ])
+            .send()?
+            .error_for_status()?
+            .text()?;
+        serde_json::from_str(&res).map_err(|e| api_err(url, res, e))
+    }
+
+    fn related(&self, tkt: &Ticket) -> Result<Vec<Issue>> {
+        self.issues_query(&format!("Vulnerability roundup {}: ", tkt.name()), 1)
+    }
+
+    fn comment(&self, index: u64, related: &[Issue]) -> Result<Comment> {
+        let url = &format!("{}/{}/comments", self.url_for.issues, index);
+        let related: Vec<String> = related.iter().map(|i| format!("#{}", i.number)).collect();
+        let res = self
+            .client
+            .post(url)
+            .json(&json!({
+                "body": format!("See also: {}", related.join(", "))
+            }))
+            .send()?
+            .error_for_status()?
+            .text()?;
+        serde_json::from_str(&res).map_err(|e| api_err(url, res, e))
+    }
+
+    fn create_and_comment(&self, tkt: &Ticket) -> Result<Issue> {
+        let i = self.create(tkt)?;
+        let rel = self.related(tkt)?;
+        if !rel.is_empty() {
+            self.comment(i.number, &rel)?;
+        }
+        Ok(i)
+    }
+
+    fn search_(&self, page: usize) -> Result<Vec<Issue>> {
+        self.issues_query("Vulnerability roundup", page)
+    }
+}
+
+// Conservative cap on the number of issues filed per run.
+const MAX_ISSUES: usize = 30;
+
+impl Tracker for Forgejo {
+    fn create_issues(&self, mut tickets: Vec<Ticket>, dir: &Path) -> Result<(), super::Error> {
+        tickets.retain(|tkt| !json_file(BACKEND, dir, tkt).exists());
+        for tkt in tickets.iter().take(MAX_ISSUES) {
+            let i = self.create_and_comment(tkt)?;
+            info!("{}: {}", tkt.name(), i.html_url.purple());
+            SavedIssue {
+                issue_id: i.number,
+                issue_url: i.url,
+                ticket: tkt.clone(),
+            }
+            .write(BACKEND, dir)
+            .map_err(|e| Error::Json(json_file(BACKEND, dir, tkt), e))?;
+        }
+        if tickets.len() > MAX_ISSUES {
+            warn!("Not all issues created due to rate limits. Wait 5 minutes and rerun with '-R'");
+        }
+        Ok(())
+    }
+
+    fn search(&self) -> Result<Vec<Issue>, super::Error> {
+        let mut iss = Vec::new();
+        for page in 1..100 {
+            let mut s = self.search_(page)?;
+            if s.is_empty() {
+                break;
+            }
+            iss.append(&mut s);
+        }
+        Ok(iss)
+    }
+
+    fn update_issue(&self, issue: &Issue, body: &str) -> Result<(), super::Error> {
+        let url = format!("{}/{}", self.url_for.issues, issue.number);
+        self.client
+            .patch(&url)
+            .json(&json!({ "body": body }))
+            .send()?
+            .error_for_status()?;
+        Ok(())
+    }
+
+    fn name(&self) -> String {
+        format!("Forgejo[{}]", self.repo)
+    }
+}
@@ -1,21 +1,19 @@
-use super::{Issue, Tracker};
+use super::{json_file, Issue, RepoSpec, SavedIssue, Tracker};
 use crate::ticket::Ticket;
 
 use clap::{crate_name, crate_version};
 use colored::*;
-use reqwest::blocking::Client;
+use reqwest::blocking::{Client, RequestBuilder, Response};
 use reqwest::header::*;
 use serde::Deserialize;
-use serde::Serialize;
-use serde_json::json;
-use std::fmt;
-use std::fs::File;
-use std::io::{BufWriter, Write};
+use serde_json::{json, Value};
 use std::path::{Path, PathBuf};
-use std::str::FromStr;
-use std::time::Duration;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use thiserror::Error;
 
+/// Backend tag `json_file`/[`SavedIssue::write`] persist under, e.g. `github.<name>.json`.
+const BACKEND: &str = "github";
+
 #[derive(Debug, Error)]
 pub enum Error {
     #[error("Invalid GitHub Api response at {url}: {resp}")]
@@ -27,8 +25,8 @@ pub enum Error {
     },
     #[error("HTTP request error")]
     Request(#[from] reqwest::Error),
-    #[error("Repository specification must be in the format <OWNER>/<REPO>")]
-    RepoFormat,
+    #[error(transparent)]
+    RepoFormat(#[from] super::RepoFormatError),
     #[error("Trying to construct invalid HTTP header")]
     Header(#[from] http::header::InvalidHeaderValue),
     #[error("Cannot write issue file '{}'", 0)]
@@ -105,20 +103,109 @@ impl GitHub {
         })
     }
 
+    /// Sends a request built by `build`, transparently waiting out GitHub's rate limits instead
+    /// of failing. `build` is called again for each retry, since a sent `RequestBuilder` is
+    /// consumed by `send`.
+    ///
+    /// A 403/429 response signals either the primary quota or the secondary abuse-detection
+    /// limit; both carry a `Retry-After` header, or failing that an `X-RateLimit-Reset`
+    /// timestamp, telling us exactly how long to wait before the request will succeed. Used for
+    /// every GitHub API call this tracker makes (issue/comment creation, search, updates), since
+    /// `search` in particular runs on every `count`/reconciliation pass and hits GitHub's
+    /// stricter search quota.
+    fn send_with_retry(&self, build: impl Fn() -> RequestBuilder) -> Result<Response> {
+        loop {
+            let res = build().send()?;
+            if let Some(wait) = Self::retry_after(&res) {
+                warn!(
+                    "GitHub rate limit hit ({}), retrying in {}s",
+                    res.status(),
+                    wait.as_secs()
+                );
+                std::thread::sleep(wait);
+                continue;
+            }
+            Self::log_quota(&res);
+            return Ok(res);
+        }
+    }
+
+    fn post(&self, url: &str, body: &Value) -> Result<String> {
+        Ok(self.send_with_retry(|| self.client.post(url).json(body))?.text()?)
+    }
+
+    fn get(&self, url: &str, query: &[(&str, String)]) -> Result<String> {
+        let res = self.send_with_retry(|| self.client.get(url).query(query))?;
+        Ok(res.error_for_status()?.text()?)
+    }
+
+    fn patch(&self, url: &str, body: &Value) -> Result<()> {
+        self.send_with_retry(|| self.client.patch(url).json(body))?
+            .error_for_status()?;
+        Ok(())
+    }
+
+    /// How long to wait before retrying, if `res` indicates a rate limit was hit.
+    fn retry_after(res: &Response) -> Option<Duration> {
+        if !matches!(res.status().as_u16(), 403 | 429) {
+            return None;
+        }
+        if let Some(secs) = res
+            .headers()
+            .get(RETRY_AFTER)
+            .and_then(|h| h.to_str().ok())
+            .and_then(|s| s.parse().ok())
+        {
+            // Retry-After only ever appears on a genuine rate-limit response.
+            return Some(Duration::from_secs(secs));
+        }
+        // x-ratelimit-* headers ride along on essentially every authenticated response, including
+        // permanent errors like a repo-permission 403 - only fall back to x-ratelimit-reset once
+        // the quota is actually exhausted, or a permanent error would compute a ~1s wait here and
+        // retry forever instead of surfacing a clear error.
+        if res
+            .headers()
+            .get("x-ratelimit-remaining")
+            .and_then(|h| h.to_str().ok())
+            != Some("0")
+        {
+            return None;
+        }
+        let reset: u64 = res
+            .headers()
+            .get("x-ratelimit-reset")
+            .and_then(|h| h.to_str().ok())
+            .and_then(|s| s.parse().ok())?;
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        Some(Duration::from_secs(reset.saturating_sub(now) + 1))
+    }
+
+    /// Logs the remaining request quota so operators can see how close a run is to the limit.
+    fn log_quota(res: &Response) {
+        if let Some(remaining) = res
+            .headers()
+            .get("x-ratelimit-remaining")
+            .and_then(|h| h.to_str().ok())
+        {
+            info!("GitHub rate limit: {} requests remaining", remaining);
+        }
+    }
+
     fn create(&self, tkt: &Ticket) -> Result<Issue> {
         let url = &self.url_for.issues;
         let mut body = String::with_capacity(4096);
         tkt.render(&mut body, self.notify).ok();
-        let res = self
-            .client
-            .post(url)
-            .json(&json!({
+        let txt = self.post(
+            url,
+            &json!({
                 "title": tkt.summary(),
                 "body": body,
                 "labels": vec!["1.severity: security"]
-            }))
-            .send()?;
-        let txt = res.text()?;
+            }),
+        )?;
         serde_json::from_str(&txt).map_err(|e| api_err(url, txt, e))
     }
 
@@ -130,29 +217,20 @@ repo:{} is:open label:\"1.severity: security\" in:title \"Vulnerability roundup
             self.repo,
             tkt.name()
         );
-        let res = self
-            .client
-            .get(url)
-            .query(&[("q", query)])
-            .send()?
-            .error_for_status()?
-            .text()?;
+        let res = self.get(url, &[("q", query)])?;
         serde_json::from_str(&res).map_err(|e| api_err(url, res, e))
     }
 
     fn comment(&self, number: u64, related: &[Issue]) -> Result<Comment> {
-        let url = &format!("{}/{}/comments", self.url_for.issues, number);
+        let url = format!("{}/{}/comments", self.url_for.issues, number);
         let related: Vec<String> = related.iter().map(|i| format!("#{}", i.number)).collect();
-        let res = self
-            .client
-            .post(url)
-            .json(&json!({
+        let txt = self.post(
+            &url,
+            &json!({
                 "body": format!("See also: {}", related.join(", "))
-            }))
-            .send()?
-            .error_for_status()?
-            .text()?;
-        serde_json::from_str(&res).map_err(|e| api_err(url, res, e))
+            }),
+        )?;
+        serde_json::from_str(&txt).map_err(|e| api_err(&url, txt, e))
     }
 
     fn create_and_comment(&self, tkt: &Ticket) -> Result<Issue> {
@@ -170,46 +248,16 @@ repo:{} is:open label:\"1.severity: security\" in:title \"Vulnerability roundup
             "repo:{} is:open label:\"1.severity: security\" in:title \"Vulnerability roundup\"",
             self.repo
         );
-        let res = self
-            .client
-            .get(url)
-            .query(&[("q", query), ("page", page.to_string())])
-            .send()?
-            .error_for_status()?
-            .text()?;
+        let res = self.get(url, &[("q", query), ("page", page.to_string())])?;
         // debug!("GitHub: {}", res);
         serde_json::from_str(&res).map_err(|e| api_err(url, res, e))
     }
 }
 
-fn json_file(dir: &Path, tkt: &Ticket) -> PathBuf {
-    dir.join(format!("github.{}.json", tkt.name()))
-}
-
-// GitHub won't accept more than 30 issues in a batch
-const MAX_ISSUES: usize = 30;
-
-#[derive(Debug, Serialize, Default)]
-struct SavedIssue {
-    ticket: Ticket,
-    issue_id: u64,
-    issue_url: String,
-}
-
-impl SavedIssue {
-    fn write(&self, dir: &Path) -> Result<(), std::io::Error> {
-        let mut f = File::create(json_file(dir, &self.ticket))?;
-        let w = BufWriter::new(f.try_clone().unwrap());
-        serde_json::to_writer_pretty(w, &self)?;
-        writeln!(f)?;
-        Ok(())
-    }
-}
-
 impl Tracker for GitHub {
     fn create_issues(&self, mut tickets: Vec<Ticket>, dir: &Path) -> Result<(), super::Error> {
-        tickets.retain(|tkt| !json_file(dir, tkt).exists());
-        for tkt in tickets.iter().take(MAX_ISSUES) {
+        tickets.retain(|tkt| !json_file(BACKEND, dir, tkt).exists());
+        for tkt in &tickets {
             let i = self.create_and_comment(tkt)?;
             info!("{}: {}", tkt.name(), i.html_url.purple());
             SavedIssue {
@@ -217,12 +265,8 @@ impl Tracker for GitHub {
                 issue_url: i.url,
                 ticket: tkt.clone(),
             }
-            .write(dir)
-            .map_err(|e| Error::Json(json_file(dir, tkt), e))?;
-            std::thread::sleep(Duration::new(1, 0));
-        }
-        if tickets.len() > MAX_ISSUES {
-            warn!("Not all issues created due to rate limits. Wait 5 minutes and rerun with '-R'");
+            .write(BACKEND, dir)
+            .map_err(|e| Error::Json(json_file(BACKEND, dir, tkt), e))?;
         }
         Ok(())
     }
@@ -239,63 +283,14 @@ impl Tracker for GitHub {
         Ok(iss)
     }
 
-    fn name(&self) -> String {
-        format!("GitHub[{}]", self.repo)
-    }
-}
-
-#[derive(Debug, Clone, PartialEq)]
-struct RepoSpec {
-    owner: String,
-    repo: String,
-}
-
-impl RepoSpec {
-    #[allow(unused)]
-    fn new<S: Into<String>, T: Into<String>>(owner: S, repo: T) -> Self {
-        Self {
-            owner: owner.into(),
-            repo: repo.into(),
-        }
-    }
-}
-
-impl FromStr for RepoSpec {
-    type Err = Error;
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let mut elem = s.split('/');
-        let owner = elem.next().ok_or(Error::RepoFormat)?.to_owned();
-        let repo = elem.next().ok_or(Error::RepoFormat)?.to_owned();
-        if owner.is_empty() || repo.is_empty() || elem.next().is_some() {
-            Err(Error::RepoFormat)
-        } else {
-            Ok(Self { owner, repo })
-        }
+    fn update_issue(&self, issue: &Issue, body: &str) -> Result<(), super::Error> {
+        let url = format!("{}/{}", self.url_for.issues, issue.number);
+        self.patch(&url, &json!({ "body": body }))?;
+        Ok(())
     }
-}
 
-impl fmt::Display for RepoSpec {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}/{}", self.owner, self.repo)
+    fn name(&self) -> String {
+        format!("GitHub[{}]", self.repo)
     }
 }
 
-#[cfg(test)]
-mod test {
-    use super::*;
-
-    #[test]
-    fn repospec_parse() {
-        assert_eq!(RepoSpec::new("foo", "bar"), "foo/bar".parse().unwrap());
-        assert!("".parse::<RepoSpec>().is_err());
-        assert!("/".parse::<RepoSpec>().is_err());
-        assert!("/foo".parse::<RepoSpec>().is_err());
-        assert!("foo/".parse::<RepoSpec>().is_err());
-        assert!("foo/bar/".parse::<RepoSpec>().is_err());
-    }
-
-    #[test]
-    fn repospec_string() {
-        assert_eq!(RepoSpec::new("owner", "repo").to_string(), "owner/repo");
-    }
-}
@@ -1,12 +1,20 @@
+mod elastic;
 mod file;
+mod forgejo;
 mod github;
 
 use crate::ticket::Ticket;
 
+pub use elastic::{Elastic, ExistsStrategy, URL_VAR};
 pub use file::File;
+pub use forgejo::{Forgejo, URL_VAR as FORGEJO_URL_VAR};
 pub use github::GitHub;
-use serde::Deserialize;
-use std::path::Path;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs::File as StdFile;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 use thiserror::Error;
 
 #[derive(Debug, Error)]
@@ -15,6 +23,100 @@ pub enum Error {
     GitHub(#[from] github::Error),
     #[error(transparent)]
     File(#[from] file::Error),
+    #[error(transparent)]
+    Elastic(#[from] elastic::Error),
+    #[error(transparent)]
+    Forgejo(#[from] forgejo::Error),
+}
+
+/// `<OWNER>/<REPO>` specifier shared by the GitHub and Forgejo/Gitea trackers, both of which
+/// address repositories the same way.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct RepoSpec {
+    owner: String,
+    repo: String,
+}
+
+impl RepoSpec {
+    #[allow(unused)]
+    fn new<S: Into<String>, T: Into<String>>(owner: S, repo: T) -> Self {
+        Self {
+            owner: owner.into(),
+            repo: repo.into(),
+        }
+    }
+}
+
+/// Error returned when a `--repo` value isn't in `<OWNER>/<REPO>` form.
+#[derive(Debug, Error)]
+#[error("Repository specification must be in the format <OWNER>/<REPO>")]
+pub(crate) struct RepoFormatError;
+
+impl FromStr for RepoSpec {
+    type Err = RepoFormatError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut elem = s.split('/');
+        let owner = elem.next().ok_or(RepoFormatError)?.to_owned();
+        let repo = elem.next().ok_or(RepoFormatError)?.to_owned();
+        if owner.is_empty() || repo.is_empty() || elem.next().is_some() {
+            Err(RepoFormatError)
+        } else {
+            Ok(Self { owner, repo })
+        }
+    }
+}
+
+impl fmt::Display for RepoSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.owner, self.repo)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn repospec_parse() {
+        assert_eq!(RepoSpec::new("foo", "bar"), "foo/bar".parse().unwrap());
+        assert!("".parse::<RepoSpec>().is_err());
+        assert!("/".parse::<RepoSpec>().is_err());
+        assert!("/foo".parse::<RepoSpec>().is_err());
+        assert!("foo/".parse::<RepoSpec>().is_err());
+        assert!("foo/bar/".parse::<RepoSpec>().is_err());
+    }
+
+    #[test]
+    fn repospec_string() {
+        assert_eq!(RepoSpec::new("owner", "repo").to_string(), "owner/repo");
+    }
+}
+
+/// Where a backend persists its "this ticket already has a filed issue" bookkeeping, so a rerun
+/// (e.g. after a rate limit forced a retry) knows not to file it again: `{backend}.{ticket
+/// name}.json` in the iteration dir.
+pub(crate) fn json_file(backend: &str, dir: &Path, tkt: &Ticket) -> PathBuf {
+    dir.join(format!("{}.{}.json", backend, tkt.name()))
+}
+
+/// Record of an already-filed issue, persisted via [`Self::write`] at `json_file`. Shared by
+/// every backend that files discrete issues (GitHub, Forgejo); [`Elastic`] indexes a fresh
+/// document each iteration instead and has no equivalent bookkeeping.
+#[derive(Debug, Serialize, Default)]
+pub(crate) struct SavedIssue {
+    pub(crate) ticket: Ticket,
+    pub(crate) issue_id: u64,
+    pub(crate) issue_url: String,
+}
+
+impl SavedIssue {
+    pub(crate) fn write(&self, backend: &str, dir: &Path) -> Result<(), std::io::Error> {
+        let mut f = StdFile::create(json_file(backend, dir, &self.ticket))?;
+        let w = BufWriter::new(f.try_clone().unwrap());
+        serde_json::to_writer_pretty(w, &self)?;
+        writeln!(f)?;
+        Ok(())
+    }
 }
 
 /// Individual issue as returned by issue search/count
@@ -34,4 +136,21 @@ pub trait Tracker {
 
     /// Returns all open isssues
     fn search(&self) -> Result<Vec<Issue>, Error>;
+
+    /// Rewrites an existing issue's body, used by the reconciliation pass in `reconcile.rs` to
+    /// merge freshly scanned state into an already-open issue instead of filing a new one.
+    fn update_issue(&self, issue: &Issue, body: &str) -> Result<(), Error>;
+
+    /// Human-readable tracker name, used for logging
+    fn name(&self) -> String;
+
+    /// Whether `reconcile.rs`'s pre-filing pass (matching fresh tickets against this tracker's
+    /// open issues by package name and merging checklist state) applies to this tracker. True for
+    /// every tracker that has an open/closed notion of an issue to merge into. [`Elastic`]
+    /// overrides this to `false`: its `search` has no such notion (it returns the full historical
+    /// corpus, not "open" documents), so reconciling against it would keep rewriting the first
+    /// iteration's document forever instead of indexing a fresh one per iteration.
+    fn reconciles(&self) -> bool {
+        true
+    }
 }